@@ -0,0 +1,71 @@
+use structopt::StructOpt as _;
+
+#[derive(Debug, structopt::StructOpt)]
+struct Opt {
+    /// Only check whether files are formatted, and exit with 2 if some are not
+    #[structopt(long = "check", alias = "test")]
+    check: bool,
+    /// Skip files and directories matching the gitignore-style pattern
+    #[structopt(long = "exclude")]
+    exclude: Vec<String>,
+    /// Load formatter options from the JSON file, or the TOML file with the fmt-toml feature
+    #[structopt(long = "config")]
+    config: Option<std::path::PathBuf>,
+    /// Files or directories to format
+    #[structopt(default_value = ".")]
+    paths: Vec<std::path::PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+
+    let options = match opt.config {
+        Some(config) => gojsonnet::FmtOptions::from_file(config)?,
+        None => gojsonnet::FmtOptions::default(),
+    };
+    let formatter = gojsonnet::Formatter::new(options);
+    let mode = if opt.check {
+        gojsonnet::FmtMode::Check
+    } else {
+        gojsonnet::FmtMode::Write
+    };
+    let dir_options = gojsonnet::FmtDirOptions {
+        mode,
+        excludes: opt.exclude,
+    };
+
+    let mut changed = 0;
+    let mut unchanged = 0;
+    let mut failed = 0;
+    for path in opt.paths {
+        let summary = formatter.format_dir(path, &dir_options)?;
+        for path in &summary.changed {
+            if opt.check {
+                println!("{}", path.display());
+            } else {
+                eprintln!("Formatted {}", path.display());
+            }
+        }
+        for (path, e) in &summary.failed {
+            eprintln!("Failed to format {}: {}", path.display(), e);
+        }
+        changed += summary.changed.len();
+        unchanged += summary.unchanged.len();
+        failed += summary.failed.len();
+    }
+
+    eprintln!(
+        "{} {}, {} unchanged, {} failed",
+        changed,
+        if opt.check { "unformatted" } else { "changed" },
+        unchanged,
+        failed
+    );
+    if failed != 0 {
+        std::process::exit(1);
+    }
+    if opt.check && changed != 0 {
+        std::process::exit(2);
+    }
+    Ok(())
+}
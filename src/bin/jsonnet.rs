@@ -0,0 +1,283 @@
+use structopt::StructOpt as _;
+
+#[derive(Debug, structopt::StructOpt)]
+#[structopt(name = "jsonnet", about = "Jsonnet interpreter using go-jsonnet")]
+struct Opt {
+    /// Specify an additional library search dir (right-most wins)
+    #[structopt(short = "J", long = "jpath", number_of_values = 1)]
+    jpath: Vec<String>,
+    /// Provide an external variable as a string, read from the environment when VALUE is omitted
+    #[structopt(
+        short = "V",
+        long = "ext-str",
+        value_name = "KEY[=VALUE]",
+        number_of_values = 1
+    )]
+    ext_str: Vec<String>,
+    /// Read an external variable as a string from the file
+    #[structopt(long = "ext-str-file", value_name = "KEY=FILE", number_of_values = 1)]
+    ext_str_file: Vec<String>,
+    /// Provide an external variable as Jsonnet code, read from the environment when CODE is
+    /// omitted
+    #[structopt(long = "ext-code", value_name = "KEY[=CODE]", number_of_values = 1)]
+    ext_code: Vec<String>,
+    /// Read an external variable as Jsonnet code from the file
+    #[structopt(long = "ext-code-file", value_name = "KEY=FILE", number_of_values = 1)]
+    ext_code_file: Vec<String>,
+    /// Provide a top-level argument as a string, read from the environment when VALUE is omitted
+    #[structopt(
+        short = "A",
+        long = "tla-str",
+        value_name = "KEY[=VALUE]",
+        number_of_values = 1
+    )]
+    tla_str: Vec<String>,
+    /// Read a top-level argument as a string from the file
+    #[structopt(long = "tla-str-file", value_name = "KEY=FILE", number_of_values = 1)]
+    tla_str_file: Vec<String>,
+    /// Provide a top-level argument as Jsonnet code, read from the environment when CODE is
+    /// omitted
+    #[structopt(long = "tla-code", value_name = "KEY[=CODE]", number_of_values = 1)]
+    tla_code: Vec<String>,
+    /// Read a top-level argument as Jsonnet code from the file
+    #[structopt(long = "tla-code-file", value_name = "KEY=FILE", number_of_values = 1)]
+    tla_code_file: Vec<String>,
+    /// Treat the argument as Jsonnet code instead of a file name
+    #[structopt(short = "e", long = "exec")]
+    exec: bool,
+    /// Write the output to the file instead of stdout. With --multi, the list of written files
+    /// is written instead
+    #[structopt(short = "o", long = "output-file")]
+    output_file: Option<std::path::PathBuf>,
+    /// Write multiple files to the directory, listing them on stdout
+    #[structopt(short = "m", long = "multi", value_name = "DIR")]
+    multi: Option<std::path::PathBuf>,
+    /// Write the output as a YAML stream of the documents in the array
+    #[structopt(short = "y", long = "yaml-stream", conflicts_with = "multi")]
+    yaml_stream: bool,
+    /// Expect a string as output and write it as is instead of JSON
+    #[structopt(short = "S", long = "string")]
+    string: bool,
+    /// Number of allowed stack frames
+    #[structopt(short = "s", long = "max-stack", value_name = "N")]
+    max_stack: Option<u32>,
+    /// Maximum number of stack frames shown in error messages (0 shows all)
+    #[structopt(short = "t", long = "max-trace", value_name = "N")]
+    max_trace: Option<u32>,
+    /// Create directories of output files if they don't exist
+    #[structopt(short = "c", long = "create-output-dirs")]
+    create_output_dirs: bool,
+    /// Write a Make-compatible depfile listing the files imported to generate the output file
+    #[structopt(
+        long = "depfile",
+        requires = "output-file",
+        conflicts_with_all = &["exec", "multi", "yaml-stream"]
+    )]
+    depfile: Option<std::path::PathBuf>,
+    filename_or_code: String,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(opt) {
+        match e.downcast_ref::<gojsonnet::Error>() {
+            Some(gojsonnet::Error::GoJsonnetError { message, .. }) => {
+                eprintln!("{}", message.trim_end())
+            }
+            _ => eprintln!("ERROR: {}", e),
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut vm = gojsonnet::Vm::default();
+    let jsonnet_path = std::env::var("JSONNET_PATH").ok();
+    for path in jpaths(jsonnet_path.as_deref(), &opt.jpath) {
+        vm.jpath_add(path)?;
+    }
+    for arg in &opt.ext_str {
+        let (key, val) = parse_var(arg)?;
+        vm.ext_var(key, &val)?;
+    }
+    for arg in &opt.ext_str_file {
+        let (key, code) = parse_file_var(arg, "importstr")?;
+        vm.ext_code(key, &code)?;
+    }
+    for arg in &opt.ext_code {
+        let (key, val) = parse_var(arg)?;
+        vm.ext_code(key, &val)?;
+    }
+    for arg in &opt.ext_code_file {
+        let (key, code) = parse_file_var(arg, "import")?;
+        vm.ext_code(key, &code)?;
+    }
+    for arg in &opt.tla_str {
+        let (key, val) = parse_var(arg)?;
+        vm.tla_var(key, &val)?;
+    }
+    for arg in &opt.tla_str_file {
+        let (key, code) = parse_file_var(arg, "importstr")?;
+        vm.tla_code(key, &code)?;
+    }
+    for arg in &opt.tla_code {
+        let (key, val) = parse_var(arg)?;
+        vm.tla_code(key, &val)?;
+    }
+    for arg in &opt.tla_code_file {
+        let (key, code) = parse_file_var(arg, "import")?;
+        vm.tla_code(key, &code)?;
+    }
+    if let Some(n) = opt.max_stack {
+        vm.max_stack(n);
+    }
+    if let Some(n) = opt.max_trace {
+        vm.max_trace(n);
+    }
+    vm.string_output(opt.string);
+
+    let filename = if opt.exec {
+        "<cmdline>"
+    } else {
+        opt.filename_or_code.as_str()
+    };
+
+    if let Some(dir) = &opt.multi {
+        let files = if opt.exec {
+            vm.evaluate_snippet_multi_raw(filename, &opt.filename_or_code)?
+        } else {
+            vm.evaluate_file_multi_raw(filename)?
+        };
+        let mut manifest = String::new();
+        for (name, content) in files {
+            let path = dir.join(name);
+            manifest.push_str(&format!("{}\n", path.display()));
+            // Keep the modification time of unchanged files for build tools
+            if std::fs::read(&path).ok().as_deref() == Some(content.as_bytes()) {
+                continue;
+            }
+            write_output(&path, &content, opt.create_output_dirs)?;
+        }
+        match &opt.output_file {
+            Some(output_file) => write_output(output_file, &manifest, opt.create_output_dirs)?,
+            None => print!("{}", manifest),
+        }
+        return Ok(());
+    }
+
+    let output = if opt.yaml_stream {
+        if opt.exec {
+            vm.evaluate_snippet_yaml_stream(filename, &opt.filename_or_code)?
+        } else {
+            vm.evaluate_file_yaml_stream(filename)?
+        }
+    } else if let Some(depfile) = &opt.depfile {
+        let traced = vm.evaluate_file_raw_traced(filename)?;
+        // --depfile requires --output-file
+        let output_file = opt.output_file.as_ref().unwrap();
+        let target = output_file.to_string_lossy();
+        write_output(
+            depfile,
+            &traced.imports.to_depfile(&target),
+            opt.create_output_dirs,
+        )?;
+        traced.value
+    } else if opt.exec {
+        vm.evaluate_snippet_raw(filename, &opt.filename_or_code)?
+    } else {
+        vm.evaluate_file_raw(filename)?
+    };
+    match &opt.output_file {
+        Some(output_file) => write_output(output_file, &output, opt.create_output_dirs)?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+/// Return library search paths in the order to add them. Left-most wins in `JSONNET_PATH`, and
+/// `-J` takes precedence over it.
+fn jpaths<'a>(jsonnet_path: Option<&'a str>, jpath: &'a [String]) -> Vec<&'a str> {
+    jsonnet_path
+        .unwrap_or_default()
+        .split(':')
+        .rev()
+        .filter(|path| !path.is_empty())
+        .chain(jpath.iter().map(String::as_str))
+        .collect()
+}
+
+/// Parse `KEY=VALUE`, or `KEY` to read the value from the environment variable.
+fn parse_var(arg: &str) -> Result<(&str, String), String> {
+    match arg.split_once('=') {
+        Some((key, val)) => Ok((key, val.to_owned())),
+        None => std::env::var(arg)
+            .map(|val| (arg, val))
+            .map_err(|_| format!("environment variable {} was undefined.", arg)),
+    }
+}
+
+/// Parse `KEY=FILE` into Jsonnet code which imports the file with `import` or `importstr`, like
+/// the upstream jsonnet command.
+fn parse_file_var<'a>(arg: &'a str, import: &str) -> Result<(&'a str, String), String> {
+    let (key, path) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not in the form of KEY=FILE", arg))?;
+    Ok((key, format!("{} @'{}'", import, path.replace('\'', "''"))))
+}
+
+fn write_output(
+    path: &std::path::Path,
+    content: &str,
+    create_dirs: bool,
+) -> Result<(), gojsonnet::Error> {
+    let io_error = |inner| gojsonnet::Error::IoError {
+        path: path.to_owned(),
+        inner,
+    };
+    if create_dirs {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+    }
+    std::fs::write(path, content).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jpaths() {
+        let jpath = vec!["c".to_owned(), "d".to_owned()];
+        assert_eq!(jpaths(Some("a:b:"), &jpath), vec!["b", "a", "c", "d"]);
+        assert_eq!(jpaths(None, &jpath), vec!["c", "d"]);
+        assert!(jpaths(Some(""), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(parse_var("foo=bar=baz"), Ok(("foo", "bar=baz".to_owned())));
+        std::env::set_var("GOJSONNET_TEST_PARSE_VAR", "from env");
+        assert_eq!(
+            parse_var("GOJSONNET_TEST_PARSE_VAR"),
+            Ok(("GOJSONNET_TEST_PARSE_VAR", "from env".to_owned()))
+        );
+        assert_eq!(
+            parse_var("GOJSONNET_TEST_UNDEFINED"),
+            Err("environment variable GOJSONNET_TEST_UNDEFINED was undefined.".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_file_var() {
+        assert_eq!(
+            parse_file_var("foo=lib/a.libsonnet", "import"),
+            Ok(("foo", "import @'lib/a.libsonnet'".to_owned()))
+        );
+        assert_eq!(
+            parse_file_var("foo=it's.txt", "importstr"),
+            Ok(("foo", "importstr @'it''s.txt'".to_owned()))
+        );
+        assert!(parse_file_var("foo", "import").is_err());
+    }
+}
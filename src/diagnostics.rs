@@ -0,0 +1,217 @@
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term;
+
+/// Source texts needed to render errors.
+///
+/// Only the code of the last evaluation is kept. Imported files are recorded by how they were
+/// resolved, and resolved again when an error is rendered so that their contents aren't kept
+/// in memory.
+#[derive(Debug, Default)]
+pub(crate) struct Sources {
+    codes: std::cell::RefCell<std::collections::HashMap<String, String>>,
+    imports: std::cell::RefCell<std::collections::HashMap<String, (String, String)>>,
+}
+impl Sources {
+    /// Forget the code of the previous evaluation and record the code of the new one.
+    pub(crate) fn reset(&self, filename: &str, code: Option<&str>) {
+        let mut codes = self.codes.borrow_mut();
+        codes.clear();
+        if let Some(code) = code {
+            codes.insert(filename.to_owned(), code.to_owned());
+        }
+    }
+
+    /// Record that `found_here` was imported as `rel` from `base`.
+    pub(crate) fn insert_import(&self, found_here: &str, base: &str, rel: &str) {
+        self.imports
+            .borrow_mut()
+            .insert(found_here.to_owned(), (base.to_owned(), rel.to_owned()));
+    }
+
+    /// Return the source text of the file. Imported files are resolved again with the resolver,
+    /// and files read by go-jsonnet itself are read from the disk again.
+    fn get(&self, filename: &str, resolver: Option<&dyn crate::ImportResolver>) -> Option<String> {
+        if let Some(code) = self.codes.borrow().get(filename) {
+            return Some(code.clone());
+        }
+        let import = self.imports.borrow().get(filename).cloned();
+        if let (Some((base, rel)), Some(resolver)) = (import, resolver) {
+            if let Ok(imported_content) = resolver.resolve(&base, &rel) {
+                if imported_content.found_here == filename {
+                    return Some(String::from_utf8_lossy(&imported_content.content).into_owned());
+                }
+            }
+        }
+        std::fs::read_to_string(filename).ok()
+    }
+}
+
+impl crate::Vm {
+    /// Render an error as annotated source snippets.
+    ///
+    /// Source texts are taken from the code given to this interpreter, the content returned
+    /// from the import callback or the files on the disk.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let e = vm
+    ///     .evaluate_snippet::<serde_json::Value>("render_error.jsonnet", "{\n  foo: bar,\n}")
+    ///     .unwrap_err();
+    /// let rendered = vm.render_error(&e);
+    /// assert!(
+    ///     rendered.contains("error[static]: Unknown variable: bar"),
+    ///     "{}",
+    ///     rendered
+    /// );
+    /// assert!(rendered.contains("render_error.jsonnet:2:8"), "{}", rendered);
+    /// assert!(rendered.contains("foo: bar"), "{}", rendered);
+    /// ```
+    pub fn render_error(&self, error: &crate::Error) -> String {
+        match error {
+            crate::Error::GoJsonnetError { details, .. } => {
+                let resolver = self
+                    .import_callback_holder
+                    .map(|holder| unsafe { (*holder).resolver.as_ref() });
+                render(&self.sources, resolver, details)
+            }
+            _ => error.to_string(),
+        }
+    }
+}
+
+fn render(
+    sources: &Sources,
+    resolver: Option<&dyn crate::ImportResolver>,
+    error: &crate::JsonnetError,
+) -> String {
+    let mut files = SimpleFiles::new();
+    let mut file_ids = std::collections::HashMap::new();
+    let mut labels = Vec::new();
+    let mut notes = Vec::new();
+
+    let mut make_label = |span: &crate::Span, primary: bool, message: &str| {
+        let file = file_ids
+            .entry(span.filename.clone())
+            .or_insert_with(|| {
+                sources
+                    .get(&span.filename, resolver)
+                    .map(|code| (files.add(span.filename.clone(), code.clone()), code))
+            })
+            .clone();
+        match file {
+            Some((file_id, code)) => {
+                let range = byte_range(&code, span);
+                let label = if primary {
+                    Label::primary(file_id, range)
+                } else {
+                    Label::secondary(file_id, range)
+                };
+                Ok(label.with_message(message))
+            }
+            None => {
+                let location = format!(
+                    "{}:{}:{}",
+                    span.filename, span.begin.line, span.begin.column
+                );
+                if message.is_empty() {
+                    Err(location)
+                } else {
+                    Err(format!("{}: {}", location, message))
+                }
+            }
+        }
+    };
+
+    let mut primary_found = false;
+    for frame in &error.stack_trace {
+        let result = match &frame.span {
+            Some(span) => {
+                let primary = !primary_found && error.span.as_ref() == Some(span);
+                primary_found |= primary;
+                make_label(span, primary, &frame.name)
+            }
+            None => Err(frame.name.clone()),
+        };
+        match result {
+            Ok(label) => labels.push(label),
+            Err(note) => notes.push(note),
+        }
+    }
+    if !primary_found {
+        if let Some(span) = &error.span {
+            match make_label(span, true, "") {
+                Ok(label) => labels.push(label),
+                Err(note) => notes.push(note),
+            }
+        }
+    }
+
+    let mut diagnostic = Diagnostic::error()
+        .with_message(&error.message)
+        .with_labels(labels)
+        .with_notes(notes);
+    diagnostic = match error.kind {
+        crate::ErrorKind::Parse => diagnostic.with_code("parse"),
+        crate::ErrorKind::Static => diagnostic.with_code("static"),
+        crate::ErrorKind::Runtime => diagnostic.with_code("runtime"),
+        crate::ErrorKind::Other => diagnostic,
+    };
+
+    let mut writer = term::termcolor::NoColor::new(Vec::new());
+    let config = term::Config::default();
+    match term::emit(&mut writer, &config, &files, &diagnostic) {
+        Ok(()) => String::from_utf8_lossy(&writer.into_inner()).into_owned(),
+        Err(_) => error.message.clone(),
+    }
+}
+
+fn byte_range(code: &str, span: &crate::Span) -> std::ops::Range<usize> {
+    let begin = byte_offset(code, &span.begin);
+    let end = byte_offset(code, &span.end);
+    begin..end.max(begin)
+}
+
+/// Convert 1-based line and column, counted in characters, to a byte offset.
+fn byte_offset(code: &str, location: &crate::Location) -> usize {
+    let line_start: usize = code
+        .split_inclusive('\n')
+        .take(location.line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let line = code[line_start..].split('\n').next().unwrap_or_default();
+    line.char_indices()
+        .nth(location.column.saturating_sub(1))
+        .map(|(i, _)| line_start + i)
+        .unwrap_or(line_start + line.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources() {
+        let sources = Sources::default();
+        sources.reset("a.jsonnet", Some("import 'b.libsonnet'"));
+        let mut importer = crate::MemoryImporter::new();
+        importer.add_file("lib/b.libsonnet", "{}");
+        sources.insert_import("lib/b.libsonnet", "lib/", "b.libsonnet");
+        assert_eq!(
+            sources.get("a.jsonnet", None).as_deref(),
+            Some("import 'b.libsonnet'")
+        );
+        assert_eq!(
+            sources.get("lib/b.libsonnet", Some(&importer)).as_deref(),
+            Some("{}")
+        );
+        assert_eq!(sources.get("lib/b.libsonnet", None), None);
+
+        sources.reset("testdata/evaluate_file.jsonnet", None);
+        assert_eq!(sources.get("a.jsonnet", None), None);
+        assert!(sources
+            .get("testdata/evaluate_file.jsonnet", None)
+            .unwrap()
+            .contains("import 'lib/evaluate_file.libsonnet'"));
+    }
+}
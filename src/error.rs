@@ -0,0 +1,281 @@
+/// Kind of errors reported by go-jsonnet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Error while lexing or parsing the code.
+    Parse,
+    /// Error found by static analysis, e.g. unknown variables.
+    Static,
+    /// Error while evaluating the code.
+    Runtime,
+    /// Other errors which go-jsonnet doesn't format in the usual way.
+    Other,
+}
+
+/// Position in a Jsonnet source. Both line and column are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Range in a Jsonnet source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub filename: String,
+    pub begin: Location,
+    /// End of the range, exclusive.
+    pub end: Location,
+}
+
+/// A frame of a stack trace of runtime errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub span: Option<Span>,
+    /// Description of the frame, e.g. `function <foo>` or `object <anonymous>`.
+    pub name: String,
+}
+
+/// Structured representation of an error message returned from go-jsonnet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonnetError {
+    pub kind: ErrorKind,
+    /// Primary message without location and stack trace.
+    pub message: String,
+    /// Location where the error happened.
+    pub span: Option<Span>,
+    /// Stack trace of runtime errors, the innermost frame first.
+    pub stack_trace: Vec<StackFrame>,
+}
+
+const RUNTIME_ERROR_PREFIX: &str = "RUNTIME ERROR: ";
+const STATIC_ERROR_PREFIX: &str = "STATIC ERROR: ";
+// go-jsonnet reports both parse errors and static analysis errors as static errors, so
+// distinguish them by the message.
+const PARSE_ERROR_PREFIXES: &[&str] = &[
+    "Expected",
+    "Unexpected",
+    "Unterminated",
+    "Could not lex",
+    "Did not expect",
+    "Truncated",
+];
+
+impl JsonnetError {
+    /// Parse an error message formatted by go-jsonnet.
+    ///
+    /// ```rust
+    /// let e = gojsonnet::JsonnetError::parse(
+    ///     "RUNTIME ERROR: foo\n\tx.jsonnet:1:1-11\t$\n\tDuring evaluation\t\n",
+    /// );
+    /// assert_eq!(e.kind, gojsonnet::ErrorKind::Runtime);
+    /// assert_eq!(e.message, "foo");
+    /// assert_eq!(e.span.unwrap().begin.column, 1);
+    /// assert_eq!(e.stack_trace.len(), 2);
+    /// ```
+    pub fn parse(raw: &str) -> Self {
+        if let Some(rest) = raw.strip_prefix(RUNTIME_ERROR_PREFIX) {
+            Self::parse_runtime(rest)
+        } else {
+            let rest = raw.strip_prefix(STATIC_ERROR_PREFIX).unwrap_or(raw);
+            Self::parse_static(rest).unwrap_or_else(|| Self {
+                kind: ErrorKind::Other,
+                message: raw.trim_end().to_owned(),
+                span: None,
+                stack_trace: Vec::new(),
+            })
+        }
+    }
+
+    fn parse_runtime(rest: &str) -> Self {
+        let mut message_lines = Vec::new();
+        let mut stack_trace = Vec::new();
+        for line in rest.lines() {
+            if let Some(frame) = line.strip_prefix('\t') {
+                if let Some(frame) = parse_stack_frame(frame) {
+                    stack_trace.push(frame);
+                }
+            } else if stack_trace.is_empty() {
+                message_lines.push(line);
+            }
+        }
+        let span = stack_trace.iter().find_map(|frame| frame.span.clone());
+        Self {
+            kind: ErrorKind::Runtime,
+            message: message_lines.join("\n"),
+            span,
+            stack_trace,
+        }
+    }
+
+    fn parse_static(rest: &str) -> Option<Self> {
+        let first_line = rest.lines().next()?;
+        // The filename may contain spaces, so try every space as a separator.
+        for (i, _) in first_line.match_indices(' ') {
+            if let Some(span) = parse_span(&first_line[..i]) {
+                let message = first_line[i + 1..].to_owned();
+                let kind = if PARSE_ERROR_PREFIXES.iter().any(|p| message.starts_with(p)) {
+                    ErrorKind::Parse
+                } else {
+                    ErrorKind::Static
+                };
+                return Some(Self {
+                    kind,
+                    message,
+                    span: Some(span),
+                    stack_trace: Vec::new(),
+                });
+            }
+        }
+        None
+    }
+}
+
+fn parse_stack_frame(frame: &str) -> Option<StackFrame> {
+    let mut it = frame.splitn(2, '\t');
+    let first = it.next().unwrap_or_default();
+    let second = it.next().unwrap_or_default().trim();
+    if let Some(span) = parse_span(first) {
+        Some(StackFrame {
+            span: Some(span),
+            name: second.to_owned(),
+        })
+    } else {
+        let name = format!("{} {}", first, second).trim().to_owned();
+        if name.is_empty() || name == "..." {
+            None
+        } else {
+            Some(StackFrame { span: None, name })
+        }
+    }
+}
+
+/// Parse a location range formatted as `file:L:C`, `file:L:C-C` or `file:(L:C)-(L:C)`.
+fn parse_span(s: &str) -> Option<Span> {
+    if s.ends_with(')') {
+        let i = s.rfind('(')?;
+        let end = parse_location(&s[i + 1..s.len() - 1])?;
+        let s = s[..i].strip_suffix(")-")?;
+        let i = s.rfind('(')?;
+        let begin = parse_location(&s[i + 1..])?;
+        let filename = s[..i].strip_suffix(':').unwrap_or(&s[..i]);
+        return Some(Span {
+            filename: filename.to_owned(),
+            begin,
+            end,
+        });
+    }
+    let mut it = s.rsplitn(3, ':');
+    let columns = it.next()?;
+    let line = it.next()?.parse().ok()?;
+    let filename = it.next().unwrap_or_default();
+    let (begin_column, end_column) = match columns.find('-') {
+        Some(i) => (columns[..i].parse().ok()?, columns[i + 1..].parse().ok()?),
+        None => {
+            let column = columns.parse().ok()?;
+            (column, column)
+        }
+    };
+    Some(Span {
+        filename: filename.to_owned(),
+        begin: Location {
+            line,
+            column: begin_column,
+        },
+        end: Location {
+            line,
+            column: end_column,
+        },
+    })
+}
+
+fn parse_location(s: &str) -> Option<Location> {
+    let mut it = s.splitn(2, ':');
+    let line = it.next()?.parse().ok()?;
+    let column = it.next()?.parse().ok()?;
+    Some(Location { line, column })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(filename: &str, begin: (usize, usize), end: (usize, usize)) -> Span {
+        Span {
+            filename: filename.to_owned(),
+            begin: Location {
+                line: begin.0,
+                column: begin.1,
+            },
+            end: Location {
+                line: end.0,
+                column: end.1,
+            },
+        }
+    }
+
+    #[test]
+    fn parse_static_error() {
+        let e =
+            JsonnetError::parse("my file.jsonnet:1:7-10 Unknown variable: bar\n\n{foo: bar}\n\n");
+        assert_eq!(
+            e,
+            JsonnetError {
+                kind: ErrorKind::Static,
+                message: "Unknown variable: bar".to_owned(),
+                span: Some(span("my file.jsonnet", (1, 7), (1, 10))),
+                stack_trace: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_parse_error() {
+        let e = JsonnetError::parse(
+            "STATIC ERROR: x.jsonnet:(1:1)-(3:2) Expected token OPERATOR but got \"}\"\n",
+        );
+        assert_eq!(e.kind, ErrorKind::Parse);
+        assert_eq!(e.message, "Expected token OPERATOR but got \"}\"");
+        assert_eq!(e.span, Some(span("x.jsonnet", (1, 1), (3, 2))));
+    }
+
+    #[test]
+    fn parse_runtime_error() {
+        let e = JsonnetError::parse(
+            "RUNTIME ERROR: lookup: key x\nnot found\n\tlib.libsonnet:2:10-25\tfunction <get>\n\tx.jsonnet:3:5\tobject <anonymous>\n\t...\n\tField \"v\"\t\n\tDuring manifestation\t\n",
+        );
+        assert_eq!(
+            e,
+            JsonnetError {
+                kind: ErrorKind::Runtime,
+                message: "lookup: key x\nnot found".to_owned(),
+                span: Some(span("lib.libsonnet", (2, 10), (2, 25))),
+                stack_trace: vec![
+                    StackFrame {
+                        span: Some(span("lib.libsonnet", (2, 10), (2, 25))),
+                        name: "function <get>".to_owned(),
+                    },
+                    StackFrame {
+                        span: Some(span("x.jsonnet", (3, 5), (3, 5))),
+                        name: "object <anonymous>".to_owned(),
+                    },
+                    StackFrame {
+                        span: None,
+                        name: "Field \"v\"".to_owned(),
+                    },
+                    StackFrame {
+                        span: None,
+                        name: "During manifestation".to_owned(),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_other_error() {
+        let e = JsonnetError::parse("something went wrong\n");
+        assert_eq!(e.kind, ErrorKind::Other);
+        assert_eq!(e.message, "something went wrong");
+        assert_eq!(e.span, None);
+    }
+}
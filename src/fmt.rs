@@ -0,0 +1,312 @@
+#[cfg(feature = "fmt-dir")]
+mod dir;
+
+use crate::{CommentStyle, Error, StringStyle};
+#[cfg(feature = "fmt-dir")]
+pub use dir::{FmtDirOptions, FmtMode, FmtSummary};
+
+/// Options of the Jsonnet formatter. Defaults are the same as go-jsonnet's.
+///
+/// Options can be loaded from JSON, or TOML with the `fmt-toml` feature.
+///
+/// ```rust
+/// let options: gojsonnet::FmtOptions =
+///     serde_json::from_str(r#"{"indent": 4, "string_style": "double"}"#).unwrap();
+/// assert_eq!(options.indent, 4);
+/// assert_eq!(options.string_style, gojsonnet::StringStyle::Double);
+/// assert_eq!(options.comment_style, gojsonnet::CommentStyle::Slash);
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FmtOptions {
+    /// Number of spaces for indentation.
+    pub indent: i32,
+    /// Maximum number of consecutive blank lines.
+    pub max_blank_lines: i32,
+    pub string_style: StringStyle,
+    pub comment_style: CommentStyle,
+    /// Whether to add spaces inside brackets of arrays.
+    pub pad_arrays: bool,
+    /// Whether to add spaces inside braces of objects.
+    pub pad_objects: bool,
+    /// Whether to remove quotes from field names when possible.
+    pub pretty_field_names: bool,
+    /// Whether to sort top-level imports in alphabetical order.
+    pub sort_imports: bool,
+}
+impl Default for FmtOptions {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            max_blank_lines: 2,
+            string_style: StringStyle::Single,
+            comment_style: CommentStyle::Slash,
+            pad_arrays: false,
+            pad_objects: true,
+            pretty_field_names: true,
+            sort_imports: true,
+        }
+    }
+}
+impl FmtOptions {
+    /// Load options from a file like `.jsonnetfmt.toml`. Files with the `.json` extension are
+    /// read as JSON, and others as TOML, which requires the `fmt-toml` feature.
+    ///
+    /// ```rust
+    /// let options = gojsonnet::FmtOptions::from_file("testdata/jsonnetfmt.json").unwrap();
+    /// assert_eq!(options.indent, 4);
+    /// assert!(!options.sort_imports);
+    /// ```
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|inner| Error::IoError {
+            path: path.to_owned(),
+            inner,
+        })?;
+        if path.extension() == Some(std::ffi::OsStr::new("json")) {
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            from_toml(path, &content)
+        }
+    }
+
+    pub(crate) unsafe fn apply(&self, vm: *mut gojsonnet_sys::JsonnetVm) {
+        gojsonnet_sys::jsonnet_fmt_indent(vm, self.indent);
+        gojsonnet_sys::jsonnet_fmt_max_blank_lines(vm, self.max_blank_lines);
+        gojsonnet_sys::jsonnet_fmt_string(vm, self.string_style.as_i32());
+        gojsonnet_sys::jsonnet_fmt_comment(vm, self.comment_style.as_i32());
+        gojsonnet_sys::jsonnet_fmt_pad_arrays(vm, self.pad_arrays as i32);
+        gojsonnet_sys::jsonnet_fmt_pad_objects(vm, self.pad_objects as i32);
+        gojsonnet_sys::jsonnet_fmt_pretty_field_names(vm, self.pretty_field_names as i32);
+        gojsonnet_sys::jsonnet_fmt_sort_imports(vm, self.sort_imports as i32);
+    }
+}
+
+#[cfg(feature = "fmt-toml")]
+fn from_toml(_path: &std::path::Path, content: &str) -> Result<FmtOptions, Error> {
+    Ok(toml::from_str(content)?)
+}
+#[cfg(not(feature = "fmt-toml"))]
+fn from_toml(path: &std::path::Path, _content: &str) -> Result<FmtOptions, Error> {
+    Err(Error::IoError {
+        path: path.to_owned(),
+        inner: std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "reading TOML requires the fmt-toml feature",
+        ),
+    })
+}
+
+/// Jsonnet formatter which owns go-jsonnet handles separately from `Vm`.
+///
+/// The formatter can be shared across threads. Each thread formatting at the same time uses its
+/// own go-jsonnet handle.
+///
+/// ```rust
+/// let mut options = gojsonnet::FmtOptions::default();
+/// options.indent = 4;
+/// let formatter = std::sync::Arc::new(gojsonnet::Formatter::new(options));
+/// let threads: Vec<_> = (0..2)
+///     .map(|_| {
+///         let formatter = formatter.clone();
+///         std::thread::spawn(move || formatter.format("formatter.jsonnet", "{x:1}").unwrap())
+///     })
+///     .collect();
+/// for thread in threads {
+///     assert_eq!(thread.join().unwrap(), "{ x: 1 }\n");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Formatter {
+    options: FmtOptions,
+    handles: std::sync::Mutex<Vec<Handle>>,
+}
+
+#[derive(Debug)]
+struct Handle(*mut gojsonnet_sys::JsonnetVm);
+// go-jsonnet handles are not bound to the thread which created them
+unsafe impl Send for Handle {}
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { gojsonnet_sys::jsonnet_destroy(self.0) };
+    }
+}
+
+impl Formatter {
+    pub fn new(options: FmtOptions) -> Self {
+        Self {
+            options,
+            handles: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn options(&self) -> &FmtOptions {
+        &self.options
+    }
+
+    /// Format a Jsonnet code.
+    ///
+    /// ```rust
+    /// let formatter = gojsonnet::Formatter::new(Default::default());
+    /// let code = formatter
+    ///     .format("format.jsonnet", "{\n'x':1,\ny:\"2\"\n}")
+    ///     .unwrap();
+    /// assert_eq!(code, "{\n  x: 1,\n  y: '2',\n}\n");
+    /// ```
+    pub fn format(&self, filename: &str, code: &str) -> Result<String, Error> {
+        let filename_cstr = std::ffi::CString::new(filename)?;
+        let code_cstr = std::ffi::CString::new(code)?;
+        let handle = self.take_handle();
+        let mut err = 0;
+        let result = unsafe {
+            let ptr = gojsonnet_sys::jsonnet_fmt_snippet(
+                handle.0,
+                filename_cstr.as_ptr(),
+                code_cstr.as_ptr(),
+                &mut err,
+            );
+            crate::take_output(handle.0, ptr, err)
+        };
+        self.lock_handles().push(handle);
+        result
+    }
+
+    /// Check whether a Jsonnet code is already formatted.
+    ///
+    /// ```rust
+    /// let formatter = gojsonnet::Formatter::default();
+    /// let check = formatter.check("check.jsonnet", "{ x: 1 }\n").unwrap();
+    /// assert!(check.is_formatted());
+    /// let check = formatter.check("check.jsonnet", "{\n'x': 1}\n").unwrap();
+    /// assert_eq!(check.formatted, "{\n  x: 1,\n}\n");
+    /// assert_eq!(
+    ///     check.diff.unwrap(),
+    ///     "--- a/check.jsonnet\n+++ b/check.jsonnet\n@@ -1,2 +1,3 @@\n {\n-'x': 1}\n+  x: 1,\n+}\n"
+    /// );
+    /// ```
+    pub fn check(&self, filename: &str, code: &str) -> Result<FmtCheck, Error> {
+        let formatted = self.format(filename, code)?;
+        let diff = if formatted == code {
+            None
+        } else {
+            Some(unified_diff(filename, code, &formatted))
+        };
+        Ok(FmtCheck { formatted, diff })
+    }
+
+    fn take_handle(&self) -> Handle {
+        if let Some(handle) = self.lock_handles().pop() {
+            return handle;
+        }
+        let handle = Handle(unsafe { gojsonnet_sys::jsonnet_make() });
+        unsafe { self.options.apply(handle.0) };
+        handle
+    }
+
+    fn lock_handles(&self) -> std::sync::MutexGuard<'_, Vec<Handle>> {
+        // Idle handles are still usable even if another thread panicked
+        self.handles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new(FmtOptions::default())
+    }
+}
+
+/// Result of `Formatter::check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FmtCheck {
+    /// Formatted code.
+    pub formatted: String,
+    /// Unified diff from the input to the formatted code, or `None` when the input is already
+    /// formatted.
+    pub diff: Option<String>,
+}
+impl FmtCheck {
+    pub fn is_formatted(&self) -> bool {
+        self.diff.is_none()
+    }
+}
+
+fn unified_diff(filename: &str, old: &str, new: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", filename), &format!("b/{}", filename))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_options() {
+        let options: FmtOptions = serde_json::from_str(
+            r#"{"max_blank_lines": 1, "comment_style": "h", "string_style": "leave", "pad_arrays": true}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            FmtOptions {
+                max_blank_lines: 1,
+                comment_style: CommentStyle::Hash,
+                string_style: StringStyle::Leave,
+                pad_arrays: true,
+                ..FmtOptions::default()
+            }
+        );
+        assert!(serde_json::from_str::<FmtOptions>(r#"{"indnet": 4}"#).is_err());
+    }
+
+    #[test]
+    fn options_from_file() {
+        let options = FmtOptions::from_file("testdata/jsonnetfmt.json").unwrap();
+        assert_eq!(
+            options,
+            FmtOptions {
+                indent: 4,
+                sort_imports: false,
+                ..FmtOptions::default()
+            }
+        );
+        assert!(matches!(
+            FmtOptions::from_file("testdata/no_such_file.toml"),
+            Err(Error::IoError { .. })
+        ));
+    }
+
+    #[cfg(feature = "fmt-toml")]
+    #[test]
+    fn options_from_toml() {
+        assert_eq!(
+            FmtOptions::from_file("testdata/jsonnetfmt.toml").unwrap(),
+            FmtOptions::from_file("testdata/jsonnetfmt.json").unwrap()
+        );
+    }
+
+    #[cfg(not(feature = "fmt-toml"))]
+    #[test]
+    fn options_from_toml() {
+        assert!(matches!(
+            FmtOptions::from_file("testdata/jsonnetfmt.toml"),
+            Err(Error::IoError { inner, .. }) if inner.kind() == std::io::ErrorKind::InvalidInput
+        ));
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(
+            unified_diff("x.jsonnet", "{}\n1\n2\n3\n4\n5\n", "{ }\n1\n2\n3\n4\n5\n"),
+            "--- a/x.jsonnet\n+++ b/x.jsonnet\n@@ -1,4 +1,4 @@\n-{}\n+{ }\n 1\n 2\n 3\n"
+        );
+        assert_eq!(
+            unified_diff("x.jsonnet", "a\n}", "a\n}\n"),
+            "--- a/x.jsonnet\n+++ b/x.jsonnet\n@@ -1,2 +1,2 @@\n a\n-}\n\\ No newline at end of file\n+}\n"
+        );
+    }
+}
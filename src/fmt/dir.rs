@@ -0,0 +1,240 @@
+use crate::{Error, Formatter};
+use rayon::iter::{IntoParallelIterator as _, ParallelIterator as _};
+
+/// What `Formatter::format_dir` does with unformatted files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FmtMode {
+    /// Rewrite unformatted files in place.
+    #[default]
+    Write,
+    /// Only report unformatted files.
+    Check,
+}
+
+/// Options of `Formatter::format_dir`.
+#[derive(Debug, Clone, Default)]
+pub struct FmtDirOptions {
+    pub mode: FmtMode,
+    /// Gitignore-style globs of files and directories to skip, in addition to `.gitignore`.
+    pub excludes: Vec<String>,
+}
+
+/// Result of `Formatter::format_dir`. Each list is sorted by the path.
+#[derive(Debug, Default)]
+pub struct FmtSummary {
+    /// Files which were rewritten, or would be rewritten in check mode.
+    pub changed: Vec<std::path::PathBuf>,
+    /// Files which were already formatted.
+    pub unchanged: Vec<std::path::PathBuf>,
+    /// Files which couldn't be read, formatted or written.
+    pub failed: Vec<(std::path::PathBuf, Error)>,
+}
+impl FmtSummary {
+    /// Whether all files were formatted successfully. In check mode, changed files are also
+    /// counted as failures.
+    pub fn is_success(&self, mode: FmtMode) -> bool {
+        self.failed.is_empty() && (mode == FmtMode::Write || self.changed.is_empty())
+    }
+}
+
+enum Outcome {
+    Changed,
+    Unchanged,
+}
+
+impl Formatter {
+    /// Format `*.jsonnet` and `*.libsonnet` files under the directory in parallel.
+    ///
+    /// Files ignored by `.gitignore`, `.ignore` and hidden files are skipped. Changed files are
+    /// replaced atomically.
+    ///
+    /// ```rust
+    /// let formatter = gojsonnet::Formatter::default();
+    /// let options = gojsonnet::FmtDirOptions {
+    ///     mode: gojsonnet::FmtMode::Check,
+    ///     excludes: vec!["jb/vendor/".to_owned()],
+    /// };
+    /// let summary = formatter.format_dir("testdata", &options).unwrap();
+    /// assert!(summary.failed.is_empty());
+    /// assert!(summary
+    ///     .unchanged
+    ///     .contains(&std::path::PathBuf::from("testdata/lib/evaluate_file.libsonnet")));
+    /// ```
+    pub fn format_dir<P>(&self, root: P, options: &FmtDirOptions) -> Result<FmtSummary, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let root = root.as_ref();
+        let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+        for pattern in &options.excludes {
+            overrides
+                .add(&format!("!{}", pattern))
+                .map_err(|inner| Error::InvalidPattern {
+                    pattern: pattern.clone(),
+                    inner,
+                })?;
+        }
+        let overrides = overrides.build().map_err(|inner| Error::InvalidPattern {
+            pattern: options.excludes.join(", "),
+            inner,
+        })?;
+
+        let mut summary = FmtSummary::default();
+        let mut paths = Vec::new();
+        for entry in ignore::WalkBuilder::new(root)
+            .require_git(false)
+            .overrides(overrides)
+            .build()
+        {
+            match entry {
+                Ok(entry) => {
+                    let path = entry.path();
+                    let is_jsonnet = matches!(
+                        path.extension().and_then(std::ffi::OsStr::to_str),
+                        Some("jsonnet") | Some("libsonnet")
+                    );
+                    if is_jsonnet && matches!(entry.file_type(), Some(t) if t.is_file()) {
+                        paths.push(entry.into_path());
+                    }
+                }
+                Err(e) => {
+                    let path = match &e {
+                        ignore::Error::WithPath { path, .. } => path.clone(),
+                        _ => root.to_owned(),
+                    };
+                    let inner = e.into_io_error().unwrap_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::Other, "failed to walk directory")
+                    });
+                    summary
+                        .failed
+                        .push((path.clone(), Error::IoError { path, inner }));
+                }
+            }
+        }
+
+        let outcomes: Vec<_> = paths
+            .into_par_iter()
+            .map(|path| {
+                let outcome = self.format_file(&path, options.mode);
+                (path, outcome)
+            })
+            .collect();
+        for (path, outcome) in outcomes {
+            match outcome {
+                Ok(Outcome::Changed) => summary.changed.push(path),
+                Ok(Outcome::Unchanged) => summary.unchanged.push(path),
+                Err(e) => summary.failed.push((path, e)),
+            }
+        }
+        summary.changed.sort();
+        summary.unchanged.sort();
+        summary.failed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(summary)
+    }
+
+    fn format_file(&self, path: &std::path::Path, mode: FmtMode) -> Result<Outcome, Error> {
+        let io_error = |inner| Error::IoError {
+            path: path.to_owned(),
+            inner,
+        };
+        let code = std::fs::read_to_string(path).map_err(io_error)?;
+        let formatted = self.format(&path.to_string_lossy(), &code)?;
+        if formatted == code {
+            return Ok(Outcome::Unchanged);
+        }
+        if mode == FmtMode::Write {
+            write_atomically(path, &formatted).map_err(io_error)?;
+        }
+        Ok(Outcome::Changed)
+    }
+}
+
+/// Replace the file with a temporary file in the same directory, keeping its permissions.
+fn write_atomically(path: &std::path::Path, content: &str) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::Write::write_all(&mut file, content.as_bytes())?;
+    file.as_file()
+        .set_permissions(std::fs::metadata(path)?.permissions())?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.jsonnet");
+        std::fs::write(&path, "{x:1}").unwrap();
+        write_atomically(&path, "{ x: 1 }\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ x: 1 }\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn format_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::create_dir_all(root.join("vendor")).unwrap();
+        std::fs::write(root.join(".gitignore"), "/generated.jsonnet\n").unwrap();
+        std::fs::write(root.join("main.jsonnet"), "{x:1}").unwrap();
+        std::fs::write(root.join("lib/a.libsonnet"), "{ a: 1 }\n").unwrap();
+        std::fs::write(root.join("lib/broken.libsonnet"), "{").unwrap();
+        std::fs::write(root.join("lib/data.json"), "{\"x\":1}").unwrap();
+        std::fs::write(root.join("generated.jsonnet"), "{x:1}").unwrap();
+        std::fs::write(root.join("vendor/b.libsonnet"), "{x:1}").unwrap();
+
+        let formatter = Formatter::default();
+        let mut options = FmtDirOptions {
+            mode: FmtMode::Check,
+            excludes: vec!["vendor/".to_owned()],
+        };
+        let summary = formatter.format_dir(root, &options).unwrap();
+        assert_eq!(summary.changed, vec![root.join("main.jsonnet")]);
+        assert_eq!(summary.unchanged, vec![root.join("lib/a.libsonnet")]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, root.join("lib/broken.libsonnet"));
+        assert!(!summary.is_success(FmtMode::Check));
+        assert_eq!(
+            std::fs::read_to_string(root.join("main.jsonnet")).unwrap(),
+            "{x:1}"
+        );
+
+        options.mode = FmtMode::Write;
+        let summary = formatter.format_dir(root, &options).unwrap();
+        assert_eq!(summary.changed, vec![root.join("main.jsonnet")]);
+        assert_eq!(
+            std::fs::read_to_string(root.join("main.jsonnet")).unwrap(),
+            "{ x: 1 }\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("generated.jsonnet")).unwrap(),
+            "{x:1}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("vendor/b.libsonnet")).unwrap(),
+            "{x:1}"
+        );
+
+        options.excludes = vec!["[".to_owned()];
+        assert!(matches!(
+            formatter.format_dir(root, &options),
+            Err(Error::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn summary_is_success() {
+        let mut summary = FmtSummary::default();
+        summary.changed.push("a.jsonnet".into());
+        assert!(summary.is_success(FmtMode::Write));
+        assert!(!summary.is_success(FmtMode::Check));
+    }
+}
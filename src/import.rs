@@ -0,0 +1,402 @@
+#[cfg(feature = "archive")]
+mod archive;
+mod cache;
+mod dir;
+#[cfg(feature = "jsonnet-bundler")]
+mod jsonnet_bundler;
+mod overlay;
+mod policy;
+
+#[cfg(feature = "archive")]
+pub use archive::ArchiveImporter;
+pub use cache::{CachingImporter, ImportCache};
+pub use dir::DirImporter;
+#[cfg(feature = "jsonnet-bundler")]
+pub use jsonnet_bundler::{JsonnetBundlerImporter, VendorMismatch};
+pub use overlay::OverlayImporter;
+pub(crate) use policy::PolicyEnforcer;
+pub use policy::{ImportLimits, ImportPolicy};
+
+/// Result of the imported content.
+pub struct ImportedContent {
+    /// Path to the imported file, absolute or relative to the process's CWD.
+    pub found_here: String,
+    /// Content of the imported file.
+    ///
+    /// `importbin` accepts arbitrary bytes except NUL, which can't be passed through the import
+    /// callback of go-jsonnet's C API.
+    pub content: Vec<u8>,
+}
+
+/// Error returned from import resolvers.
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    /// The imported file is not found in any search paths.
+    #[error("couldn't open import {rel:?}: no match locally or in the Jsonnet library paths")]
+    NotFound { rel: String },
+    /// Error while reading the imported file.
+    #[error("couldn't open import {path:?}: {inner}")]
+    IoError {
+        path: String,
+        #[source]
+        inner: std::io::Error,
+    },
+    /// The import is rejected by `ImportPolicy`.
+    #[error("import {rel:?} denied by the import policy: {reason}")]
+    PolicyViolation { rel: String, reason: String },
+    /// Other errors.
+    #[error("{message}")]
+    Other { message: String },
+}
+impl From<String> for ImportError {
+    fn from(message: String) -> Self {
+        Self::Other { message }
+    }
+}
+impl From<&str> for ImportError {
+    fn from(message: &str) -> Self {
+        Self::Other {
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Where `ImportResolver::locate` found a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Located {
+    /// The file is on the disk at the path, which `resolve` returns as `found_here`.
+    File(String),
+    /// The file is not found.
+    NotFound,
+    /// The resolver can't tell without resolving the import, e.g. because the file isn't on
+    /// the disk.
+    Unknown,
+}
+
+/// Resolver of `import`, `importstr` and `importbin`.
+pub trait ImportResolver {
+    /// Resolve `rel` imported from a file in the directory `base`.
+    ///
+    /// `base` is empty when the importing code doesn't have a directory part, e.g. a snippet
+    /// named `foo.jsonnet`.
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError>;
+
+    /// Resolve `rel` under the limits of `ImportPolicy`.
+    ///
+    /// The default implementation checks the file after `resolve` returns it. Resolvers reading
+    /// files from the disk should override this and read them with `ImportLimits::read_file`.
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        let imported_content = self.resolve(base, rel)?;
+        limits.check(&imported_content)?;
+        Ok(imported_content)
+    }
+
+    /// Find the file which `resolve` would return for `rel` without reading it.
+    ///
+    /// `CachingImporter` serves cached files only for resolvers implementing this, and
+    /// `OverlayImporter` needs it from its layers to tell which layer serves the file.
+    fn locate(&self, _base: &str, _rel: &str) -> Located {
+        Located::Unknown
+    }
+}
+impl<F> ImportResolver for F
+where
+    F: Fn(&str, &str) -> Result<ImportedContent, ImportError>,
+{
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        self(base, rel)
+    }
+}
+
+/// Import resolver which reads files from the disk in the same order as go-jsonnet's default
+/// importer.
+///
+/// The directory of the importing file is searched first, then library search paths are
+/// searched from the last added one.
+///
+/// ```rust
+/// let mut importer = gojsonnet::FileImporter::new();
+/// importer.jpath_add("testdata/lib");
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(importer);
+/// let s: String = vm
+///     .evaluate_snippet(
+///         "file_importer.jsonnet",
+///         "(import 'evaluate_file.libsonnet').name",
+///     )
+///     .unwrap();
+/// assert_eq!(s, "imported");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FileImporter {
+    jpaths: Vec<String>,
+}
+impl FileImporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a library search path.
+    pub fn jpath_add<S>(&mut self, path: S)
+    where
+        S: Into<String>,
+    {
+        self.jpaths.push(path.into());
+    }
+
+    /// Read the first candidate file found with `read`.
+    fn find<F>(&self, base: &str, rel: &str, read: F) -> Result<ImportedContent, ImportError>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ImportError>,
+    {
+        for path in candidate_paths(base, rel, &self.jpaths) {
+            match read(&path) {
+                Ok(content) => {
+                    return Ok(ImportedContent {
+                        found_here: path,
+                        content,
+                    })
+                }
+                Err(ImportError::IoError { inner, .. })
+                    if inner.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(ImportError::NotFound {
+            rel: rel.to_owned(),
+        })
+    }
+}
+impl ImportResolver for FileImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        self.find(base, rel, |path| {
+            std::fs::read(path).map_err(|inner| ImportError::IoError {
+                path: path.to_owned(),
+                inner,
+            })
+        })
+    }
+
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        self.find(base, rel, |path| limits.read_file(path))
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        for path in candidate_paths(base, rel, &self.jpaths) {
+            match std::fs::metadata(&path) {
+                Ok(_) => return Located::File(path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                // Let resolve report the error
+                Err(_) => return Located::Unknown,
+            }
+        }
+        Located::NotFound
+    }
+}
+
+/// Import resolver which serves files from memory.
+///
+/// Paths are searched in the same order as `FileImporter`.
+///
+/// ```rust
+/// let mut importer = gojsonnet::MemoryImporter::new();
+/// importer.add_file("lib/a.libsonnet", "(import '../b.libsonnet') + 1");
+/// importer.add_file("b.libsonnet", "2");
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(importer);
+/// let v: i32 = vm
+///     .evaluate_snippet("memory_importer.jsonnet", "import 'lib/a.libsonnet'")
+///     .unwrap();
+/// assert_eq!(v, 3);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryImporter {
+    files: std::collections::HashMap<String, Vec<u8>>,
+    jpaths: Vec<String>,
+}
+impl MemoryImporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file. The path is cleaned in the same way as import paths.
+    pub fn add_file<C>(&mut self, path: &str, content: C)
+    where
+        C: Into<Vec<u8>>,
+    {
+        self.files.insert(clean_path(path), content.into());
+    }
+
+    /// Add a library search path.
+    pub fn jpath_add<S>(&mut self, path: S)
+    where
+        S: Into<String>,
+    {
+        self.jpaths.push(path.into());
+    }
+}
+impl ImportResolver for MemoryImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        for path in candidate_paths(base, rel, &self.jpaths) {
+            if let Some(content) = self.files.get(&path) {
+                return Ok(ImportedContent {
+                    found_here: path,
+                    content: content.clone(),
+                });
+            }
+        }
+        Err(ImportError::NotFound {
+            rel: rel.to_owned(),
+        })
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        if candidate_paths(base, rel, &self.jpaths).any(|path| self.files.contains_key(&path)) {
+            Located::Unknown
+        } else {
+            Located::NotFound
+        }
+    }
+}
+
+/// Return paths to search in the same order as go-jsonnet: the directory of the importing file
+/// first, then library search paths from the last added one.
+fn candidate_paths<'a>(
+    base: &'a str,
+    rel: &'a str,
+    jpaths: &'a [String],
+) -> impl Iterator<Item = String> + 'a {
+    std::iter::once(join_path(base, rel))
+        .chain(jpaths.iter().rev().map(move |jpath| join_path(jpath, rel)))
+}
+
+/// Join slash-separated paths and clean the result lexically like Go's `path.Join`.
+pub(crate) fn join_path(dir: &str, rel: &str) -> String {
+    if rel.starts_with('/') || dir.is_empty() {
+        clean_path(rel)
+    } else {
+        clean_path(&format!("{}/{}", dir, rel))
+    }
+}
+
+/// Clean a slash-separated path lexically like Go's `path.Clean`.
+pub(crate) fn clean_path(path: &str) -> String {
+    let rooted = path.starts_with('/');
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => match components.last() {
+                Some(&last) if last != ".." => {
+                    components.pop();
+                }
+                _ => {
+                    if !rooted {
+                        components.push("..");
+                    }
+                }
+            },
+            _ => components.push(component),
+        }
+    }
+    let joined = components.join("/");
+    if rooted {
+        format!("/{}", joined)
+    } else if joined.is_empty() {
+        ".".to_owned()
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_path() {
+        assert_eq!(join_path("", "foo.libsonnet"), "foo.libsonnet");
+        assert_eq!(join_path("lib/", "foo.libsonnet"), "lib/foo.libsonnet");
+        assert_eq!(
+            join_path("lib/a/", "../b/./foo.libsonnet"),
+            "lib/b/foo.libsonnet"
+        );
+        assert_eq!(
+            join_path("lib/", "/abs/foo.libsonnet"),
+            "/abs/foo.libsonnet"
+        );
+        assert_eq!(join_path("lib/", "../../foo.libsonnet"), "../foo.libsonnet");
+    }
+
+    #[test]
+    fn test_clean_path() {
+        assert_eq!(clean_path(""), ".");
+        assert_eq!(clean_path("./"), ".");
+        assert_eq!(clean_path("/../a//b/"), "/a/b");
+        assert_eq!(clean_path("a/../.."), "..");
+    }
+
+    #[test]
+    fn memory_importer() {
+        let mut importer = MemoryImporter::new();
+        importer.add_file("./vendor/k.libsonnet", "{}");
+        importer.add_file("app/main.jsonnet", "import 'k.libsonnet'");
+        importer.add_file("app/k.libsonnet", "{ local: true }");
+        importer.jpath_add("vendor");
+
+        let imported_content = importer.resolve("app/", "k.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "app/k.libsonnet");
+        assert_eq!(imported_content.content, b"{ local: true }");
+        let imported_content = importer.resolve("", "k.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "vendor/k.libsonnet");
+        let imported_content = importer.resolve("app/", "../vendor/k.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "vendor/k.libsonnet");
+        assert!(matches!(
+            importer.resolve("app/", "main.libsonnet"),
+            Err(ImportError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn file_importer_not_found() {
+        let mut importer = FileImporter::new();
+        importer.jpath_add("testdata");
+        let e = importer
+            .resolve("", "no_such_file.libsonnet")
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            "couldn't open import \"no_such_file.libsonnet\": no match locally or in the Jsonnet library paths"
+        );
+    }
+
+    #[test]
+    fn file_importer_search_order() {
+        let mut importer = FileImporter::new();
+        importer.jpath_add("testdata");
+        importer.jpath_add("testdata/lib");
+        let imported_content = importer.resolve("", "evaluate_file.libsonnet").unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "testdata/lib/evaluate_file.libsonnet"
+        );
+        let imported_content = importer
+            .resolve("testdata/", "evaluate_file.jsonnet")
+            .unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "testdata/evaluate_file.jsonnet"
+        );
+    }
+}
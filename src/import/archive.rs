@@ -0,0 +1,418 @@
+use super::{clean_path, join_path, ImportError, ImportResolver, ImportedContent, Located};
+use crate::trace::dir_of;
+
+/// Import resolver which serves files from a `.tar`, `.tar.gz` or `.zip` archive.
+///
+/// The archive works like a library search path. Imported files are reported as
+/// `archive.tar!/path/lib.libsonnet` and relative imports from them are resolved inside the
+/// archive.
+///
+/// Symbolic links and hard links in the archive are followed, like the ones jsonnet-bundler
+/// creates for legacy import paths. Links pointing outside the archive are reported as errors.
+///
+/// ```rust,no_run
+/// let mut importer = gojsonnet::ArchiveImporter::open("vendor.tar.gz").unwrap();
+/// importer.mount("github.com/grafana/jsonnet-libs");
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(importer);
+/// let v: serde_json::Value = vm
+///     .evaluate_snippet(
+///         "archive_importer.jsonnet",
+///         "import 'github.com/grafana/jsonnet-libs/ksonnet-util/kausal.libsonnet'",
+///     )
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArchiveImporter {
+    name: String,
+    prefix: String,
+    files: std::collections::HashMap<String, Vec<u8>>,
+    /// Links and their targets, both relative to the root of the archive.
+    links: std::collections::HashMap<String, String>,
+}
+
+/// Maximum number of links followed to resolve a path, same as Linux.
+const MAX_LINKS: usize = 40;
+
+impl ArchiveImporter {
+    /// Open an archive. The format is detected by the extension: `.tar`, `.tar.gz`, `.tgz` or
+    /// `.zip`.
+    pub fn open<P>(path: P) -> Result<Self, ImportError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let path = path.as_ref();
+        let name = path.to_string_lossy().into_owned();
+        let io_error = |inner| ImportError::IoError {
+            path: name.clone(),
+            inner,
+        };
+        let file = std::io::BufReader::new(std::fs::File::open(path).map_err(io_error)?);
+        if name.ends_with(".tar") {
+            Self::from_tar(&name, file).map_err(io_error)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Self::from_tar(&name, flate2::read::GzDecoder::new(file)).map_err(io_error)
+        } else if name.ends_with(".zip") {
+            Self::from_zip(&name, file).map_err(io_error)
+        } else {
+            Err(io_error(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "unknown archive format",
+            )))
+        }
+    }
+
+    /// Read a tar archive. `name` is used as the prefix of `found_here`.
+    pub fn from_tar<R>(name: &str, reader: R) -> std::io::Result<Self>
+    where
+        R: std::io::Read,
+    {
+        let mut importer = Self::new(name);
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                if let Some(link_name) = entry.link_name()? {
+                    let link_name = link_name.to_string_lossy();
+                    // Targets of hard links are relative to the root of the archive
+                    let base = if entry_type.is_symlink() {
+                        dir_of(&path)
+                    } else {
+                        ""
+                    };
+                    importer.add_link(&path, join_path(base, &link_name));
+                }
+                continue;
+            }
+            if !entry_type.is_file() {
+                continue;
+            }
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content)?;
+            importer.add_file(&path, content);
+        }
+        Ok(importer)
+    }
+
+    /// Read a zip archive. `name` is used as the prefix of `found_here`.
+    pub fn from_zip<R>(name: &str, reader: R) -> std::io::Result<Self>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let mut importer = Self::new(name);
+        let mut archive = zip::ZipArchive::new(reader)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if !file.is_file() {
+                continue;
+            }
+            let path = file.name().to_owned();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut content)?;
+            // Symbolic links are stored with their targets as the content
+            if file
+                .unix_mode()
+                .map_or(false, |mode| mode & 0o170000 == 0o120000)
+            {
+                let target = String::from_utf8_lossy(&content);
+                importer.add_link(&path, join_path(dir_of(&path), &target));
+            } else {
+                importer.add_file(&path, content);
+            }
+        }
+        Ok(importer)
+    }
+
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            prefix: ".".to_owned(),
+            files: std::collections::HashMap::new(),
+            links: std::collections::HashMap::new(),
+        }
+    }
+
+    fn add_file(&mut self, path: &str, content: Vec<u8>) {
+        let path = clean_path(path);
+        // Ignore entries escaping from the archive
+        if !path.starts_with('/') && !path.starts_with("../") {
+            self.files.insert(path, content);
+        }
+    }
+
+    fn add_link(&mut self, path: &str, target: String) {
+        let path = clean_path(path);
+        if !path.starts_with('/') && !path.starts_with("../") {
+            self.links.insert(path, target);
+        }
+    }
+
+    /// Replace links in `path` with their targets.
+    fn follow_links(&self, path: &str) -> Result<String, ImportError> {
+        let mut path = path.to_owned();
+        for _ in 0..MAX_LINKS {
+            // Find the shortest prefix of the path which is a link
+            let link = path
+                .match_indices('/')
+                .map(|(i, _)| (&path[..i], &path[i + 1..]))
+                .chain(std::iter::once((path.as_str(), "")))
+                .find_map(|(prefix, rest)| Some((prefix, self.links.get(prefix)?, rest)));
+            let (link, target, rest) = match link {
+                Some(link) => link,
+                None => return Ok(path),
+            };
+            if target.starts_with('/') || target == ".." || target.starts_with("../") {
+                return Err(self.link_error(link, "link points outside the archive"));
+            }
+            path = join_path(target, rest);
+        }
+        Err(self.link_error(&path, "too many levels of links"))
+    }
+
+    fn link_error(&self, path: &str, message: &str) -> ImportError {
+        ImportError::IoError {
+            path: format!("{}!/{}", self.name, path),
+            inner: std::io::Error::new(std::io::ErrorKind::InvalidData, message),
+        }
+    }
+
+    /// Mount the archive under the given import path prefix.
+    pub fn mount<S>(&mut self, prefix: S)
+    where
+        S: Into<String>,
+    {
+        self.prefix = clean_path(&prefix.into());
+    }
+
+    /// Return the path inside the archive for `rel` imported from `base`.
+    fn path_in_archive(&self, base: &str, rel: &str) -> Option<String> {
+        let archive_base = format!("{}!/", self.name);
+        if let Some(inner_base) = base.strip_prefix(&archive_base) {
+            Some(join_path(inner_base, rel))
+        } else if rel.starts_with('/') {
+            None
+        } else {
+            let path = clean_path(rel);
+            if self.prefix == "." {
+                Some(path)
+            } else {
+                path.strip_prefix(&self.prefix)
+                    .and_then(|p| p.strip_prefix('/'))
+                    .map(str::to_owned)
+            }
+        }
+    }
+
+    /// Return the file at `path`, which is reported as `found_here` even if it is a link.
+    fn get(&self, path: &str) -> Result<Option<ImportedContent>, ImportError> {
+        let target = self.follow_links(path)?;
+        Ok(self.files.get(&target).map(|content| ImportedContent {
+            found_here: format!("{}!/{}", self.name, path),
+            content: content.clone(),
+        }))
+    }
+}
+impl ImportResolver for ArchiveImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        let found = match self.path_in_archive(base, rel) {
+            Some(path) => self.get(&path)?,
+            None => None,
+        };
+        found.ok_or_else(|| ImportError::NotFound {
+            rel: rel.to_owned(),
+        })
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        let path = match self.path_in_archive(base, rel) {
+            Some(path) => path,
+            None => return Located::NotFound,
+        };
+        match self.follow_links(&path) {
+            Ok(target) if !self.files.contains_key(&target) => Located::NotFound,
+            // Files in the archive aren't on the disk, and resolve reports broken links
+            _ => Located::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in &[
+            ("./lib/a.libsonnet", &b"import '../b.libsonnet'"[..]),
+            ("b.libsonnet", &b"{}"[..]),
+            ("latin1.txt", &b"caf\xe9"[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn tar_importer() {
+        let importer = ArchiveImporter::from_tar("vendor.tar", &tar_archive()[..]).unwrap();
+        let imported_content = importer.resolve("", "lib/a.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "vendor.tar!/lib/a.libsonnet");
+        assert_eq!(imported_content.content, b"import '../b.libsonnet'");
+        let imported_content = importer
+            .resolve("vendor.tar!/lib/", "../b.libsonnet")
+            .unwrap();
+        assert_eq!(imported_content.found_here, "vendor.tar!/b.libsonnet");
+        assert!(importer
+            .resolve("vendor.tar!/lib/", "../../b.libsonnet")
+            .is_err());
+        assert!(importer.resolve("", "/b.libsonnet").is_err());
+        assert_eq!(
+            importer.resolve("", "latin1.txt").unwrap().content,
+            b"caf\xe9"
+        );
+    }
+
+    #[test]
+    fn tar_links() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut append = |entry_type, path: &str, link_name: &str, content: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            if !link_name.is_empty() {
+                header.set_link_name(link_name).unwrap();
+            }
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        };
+        let (regular, symlink, link) = (
+            tar::EntryType::Regular,
+            tar::EntryType::Symlink,
+            tar::EntryType::Link,
+        );
+        append(
+            regular,
+            "github.com/grafana/jsonnet-libs/ksonnet-util/kausal.libsonnet",
+            "",
+            b"import 'util.libsonnet'",
+        );
+        append(
+            regular,
+            "github.com/grafana/jsonnet-libs/ksonnet-util/util.libsonnet",
+            "",
+            b"{}",
+        );
+        // Legacy import paths created by jsonnet-bundler
+        append(
+            symlink,
+            "ksonnet-util",
+            "github.com/grafana/jsonnet-libs/ksonnet-util",
+            b"",
+        );
+        append(
+            link,
+            "hard.libsonnet",
+            "github.com/grafana/jsonnet-libs/ksonnet-util/util.libsonnet",
+            b"",
+        );
+        append(symlink, "escape", "../outside", b"");
+        append(symlink, "absolute.libsonnet", "/etc/passwd", b"");
+        append(symlink, "loop", "loop/a", b"");
+        append(symlink, "dangling.libsonnet", "missing.libsonnet", b"");
+        let archive = builder.into_inner().unwrap();
+        let importer = ArchiveImporter::from_tar("vendor.tar", &archive[..]).unwrap();
+
+        let imported_content = importer
+            .resolve("", "ksonnet-util/kausal.libsonnet")
+            .unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "vendor.tar!/ksonnet-util/kausal.libsonnet"
+        );
+        assert_eq!(imported_content.content, b"import 'util.libsonnet'");
+        let imported_content = importer
+            .resolve("vendor.tar!/ksonnet-util/", "util.libsonnet")
+            .unwrap();
+        assert_eq!(imported_content.content, b"{}");
+        let imported_content = importer.resolve("", "hard.libsonnet").unwrap();
+        assert_eq!(imported_content.content, b"{}");
+        assert_eq!(
+            importer.locate("", "ksonnet-util/util.libsonnet"),
+            Located::Unknown
+        );
+
+        for rel in &["escape/a.libsonnet", "absolute.libsonnet", "loop/a"] {
+            assert!(
+                matches!(importer.resolve("", rel), Err(ImportError::IoError { .. })),
+                "{}",
+                rel
+            );
+        }
+        assert!(matches!(
+            importer.resolve("", "dangling.libsonnet"),
+            Err(ImportError::NotFound { .. })
+        ));
+        assert_eq!(importer.locate("", "dangling.libsonnet"), Located::NotFound);
+    }
+
+    #[test]
+    fn tar_gz_importer() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar_archive()).unwrap();
+        let gz = encoder.finish().unwrap();
+        let importer =
+            ArchiveImporter::from_tar("vendor.tar.gz", flate2::read::GzDecoder::new(&gz[..]))
+                .unwrap();
+        let imported_content = importer.resolve("", "b.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "vendor.tar.gz!/b.libsonnet");
+    }
+
+    #[test]
+    fn zip_importer() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file("ksonnet-util/kausal.libsonnet", Default::default())
+            .unwrap();
+        std::io::Write::write_all(&mut writer, b"{ kausal: true }").unwrap();
+        writer
+            .add_symlink(
+                "legacy/kausal.libsonnet",
+                "../ksonnet-util/kausal.libsonnet",
+                Default::default(),
+            )
+            .unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        let mut importer =
+            ArchiveImporter::from_zip("vendor.zip", std::io::Cursor::new(zip)).unwrap();
+        importer.mount("github.com/grafana/jsonnet-libs/");
+        let imported_content = importer
+            .resolve(
+                "",
+                "github.com/grafana/jsonnet-libs/ksonnet-util/kausal.libsonnet",
+            )
+            .unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "vendor.zip!/ksonnet-util/kausal.libsonnet"
+        );
+        assert_eq!(imported_content.content, b"{ kausal: true }");
+        assert!(importer
+            .resolve("", "ksonnet-util/kausal.libsonnet")
+            .is_err());
+        let imported_content = importer
+            .resolve(
+                "",
+                "github.com/grafana/jsonnet-libs/legacy/kausal.libsonnet",
+            )
+            .unwrap();
+        assert_eq!(imported_content.content, b"{ kausal: true }");
+    }
+}
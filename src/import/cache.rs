@@ -0,0 +1,392 @@
+use super::{ImportError, ImportLimits, ImportResolver, ImportedContent, Located};
+
+/// Contents of imported files shared by `CachingImporter`s, keyed by the path of the file.
+///
+/// Entries are invalidated when the modification time or the size of the file changes. Files
+/// which change while they are read aren't cached. Files with the same content, like copies of a library vendored by many projects, are found by the
+/// hash of the content and share the memory.
+#[derive(Debug, Default)]
+pub struct ImportCache {
+    entries: std::sync::Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    files: std::collections::HashMap<String, CachedFile>,
+    /// Cached contents by their hash.
+    contents: std::collections::HashMap<u64, std::sync::Weak<[u8]>>,
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    stamp: Stamp,
+    content: std::sync::Arc<[u8]>,
+}
+
+/// Modification time and size of a file on the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<std::time::SystemTime>,
+    len: u64,
+}
+impl Stamp {
+    fn read(path: &str) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+impl ImportCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove all cached files.
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        entries.files.clear();
+        entries.contents.clear();
+    }
+
+    /// Return the number of cached files.
+    pub fn len(&self) -> usize {
+        self.lock().files.len()
+    }
+
+    /// Return `true` when no files are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // The cache is still consistent even if another thread panicked
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, found_here: &str, stamp: Stamp) -> Option<std::sync::Arc<[u8]>> {
+        let entries = self.lock();
+        let file = entries.files.get(found_here)?;
+        if file.stamp == stamp {
+            Some(file.content.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Cache a file read after `stamp` was taken from `found_here`.
+    fn insert(&self, found_here: &str, stamp: Stamp, imported_content: &ImportedContent) {
+        // The resolver returned another file, or the file changed while it was read
+        if imported_content.found_here != found_here || Stamp::read(found_here) != Some(stamp) {
+            return;
+        }
+        let hash = {
+            use std::hash::{Hash as _, Hasher as _};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            imported_content.content.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut entries = self.lock();
+        let content = match entries
+            .contents
+            .get(&hash)
+            .and_then(std::sync::Weak::upgrade)
+        {
+            Some(content) if *content == *imported_content.content => content,
+            _ => {
+                let content: std::sync::Arc<[u8]> = imported_content.content.as_slice().into();
+                entries
+                    .contents
+                    .insert(hash, std::sync::Arc::downgrade(&content));
+                content
+            }
+        };
+        let replaced = entries
+            .files
+            .insert(
+                imported_content.found_here.clone(),
+                CachedFile { stamp, content },
+            )
+            .is_some();
+        if replaced {
+            // Forget contents which are no longer used by any file
+            entries
+                .contents
+                .retain(|_, content| content.strong_count() != 0);
+        }
+    }
+}
+
+/// Import resolver which caches files read by another resolver.
+///
+/// The cache can be shared by resolvers of many interpreters, even across threads. Import
+/// paths are resolved by the inner resolver on every import with `ImportResolver::locate`, so
+/// resolvers with different search paths can share a cache, and a file added to an earlier
+/// search path is noticed. Only files found on the disk by resolvers implementing `locate`,
+/// like `FileImporter`, are served from the cache.
+///
+/// ```rust
+/// let cache = std::sync::Arc::new(gojsonnet::ImportCache::new());
+/// for _ in 0..2 {
+///     let mut importer = gojsonnet::FileImporter::new();
+///     importer.jpath_add("testdata");
+///     let mut vm = gojsonnet::Vm::default();
+///     vm.import_resolver(gojsonnet::CachingImporter::new(importer, cache.clone()));
+///     let s: String = vm
+///         .evaluate_snippet(
+///             "caching_importer.jsonnet",
+///             "(import 'lib/evaluate_file.libsonnet').name",
+///         )
+///         .unwrap();
+///     assert_eq!(s, "imported");
+/// }
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct CachingImporter<R> {
+    inner: R,
+    cache: std::sync::Arc<ImportCache>,
+}
+impl<R> CachingImporter<R>
+where
+    R: ImportResolver,
+{
+    pub fn new(inner: R, cache: std::sync::Arc<ImportCache>) -> Self {
+        Self { inner, cache }
+    }
+
+    /// Locate the file and take its stamp before the inner resolver reads it.
+    fn stamp(&self, base: &str, rel: &str) -> Option<(String, Stamp)> {
+        let found_here = match self.inner.locate(base, rel) {
+            Located::File(found_here) => found_here,
+            Located::NotFound | Located::Unknown => return None,
+        };
+        // Files which don't exist on the disk can't be invalidated
+        let stamp = Stamp::read(&found_here)?;
+        Some((found_here, stamp))
+    }
+
+    fn cached(&self, located: &Option<(String, Stamp)>) -> Option<ImportedContent> {
+        let (found_here, stamp) = located.as_ref()?;
+        let content = self.cache.get(found_here, *stamp)?;
+        Some(ImportedContent {
+            found_here: found_here.clone(),
+            content: content.to_vec(),
+        })
+    }
+
+    fn insert(&self, located: Option<(String, Stamp)>, imported_content: &ImportedContent) {
+        if let Some((found_here, stamp)) = located {
+            self.cache.insert(&found_here, stamp, imported_content);
+        }
+    }
+}
+impl<R> ImportResolver for CachingImporter<R>
+where
+    R: ImportResolver,
+{
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        let located = self.stamp(base, rel);
+        if let Some(imported_content) = self.cached(&located) {
+            return Ok(imported_content);
+        }
+        let imported_content = self.inner.resolve(base, rel)?;
+        self.insert(located, &imported_content);
+        Ok(imported_content)
+    }
+
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        let located = self.stamp(base, rel);
+        if let Some(imported_content) = self.cached(&located) {
+            limits.check(&imported_content)?;
+            return Ok(imported_content);
+        }
+        let imported_content = self.inner.resolve_with_limits(base, rel, limits)?;
+        self.insert(located, &imported_content);
+        Ok(imported_content)
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        self.inner.locate(base, rel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingImporter {
+        inner: crate::FileImporter,
+        count: std::cell::Cell<usize>,
+    }
+    impl ImportResolver for CountingImporter {
+        fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+            self.count.set(self.count.get() + 1);
+            self.inner.resolve(base, rel)
+        }
+
+        fn locate(&self, base: &str, rel: &str) -> Located {
+            self.inner.locate(base, rel)
+        }
+    }
+
+    #[test]
+    fn invalidate() {
+        let dir = std::env::temp_dir().join(format!("gojsonnet-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.libsonnet");
+        std::fs::write(&path, "1").unwrap();
+        let base = format!("{}/", dir.to_str().unwrap());
+
+        let cache = std::sync::Arc::new(ImportCache::new());
+        let importer = CachingImporter::new(
+            CountingImporter {
+                inner: crate::FileImporter::new(),
+                count: std::cell::Cell::new(0),
+            },
+            cache.clone(),
+        );
+        assert_eq!(
+            importer.resolve(&base, "a.libsonnet").unwrap().content,
+            b"1"
+        );
+        assert_eq!(
+            importer.resolve(&base, "a.libsonnet").unwrap().content,
+            b"1"
+        );
+        assert_eq!(importer.inner.count.get(), 1);
+
+        std::fs::write(&path, "22").unwrap();
+        assert_eq!(
+            importer.resolve(&base, "a.libsonnet").unwrap().content,
+            b"22"
+        );
+        assert_eq!(importer.inner.count.get(), 2);
+        assert_eq!(cache.len(), 1);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_paths() {
+        let dir =
+            std::env::temp_dir().join(format!("gojsonnet-cache-jpath-{}", std::process::id()));
+        for name in &["a", "b", "main"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        std::fs::write(dir.join("a/lib.libsonnet"), "'a'").unwrap();
+        std::fs::write(dir.join("b/lib.libsonnet"), "'b'").unwrap();
+        let base = format!("{}/", dir.join("main").to_str().unwrap());
+
+        // Resolvers with different search paths get their own files from the shared cache
+        let cache = std::sync::Arc::new(ImportCache::new());
+        let importer = |jpath: &str| {
+            let mut inner = crate::FileImporter::new();
+            inner.jpath_add(dir.join(jpath).to_str().unwrap());
+            CachingImporter::new(inner, cache.clone())
+        };
+        let (a, b) = (importer("a"), importer("b"));
+        for _ in 0..2 {
+            assert_eq!(a.resolve(&base, "lib.libsonnet").unwrap().content, b"'a'");
+            assert_eq!(b.resolve(&base, "lib.libsonnet").unwrap().content, b"'b'");
+        }
+        assert_eq!(cache.len(), 2);
+
+        // A file added to an earlier search path wins
+        std::fs::write(dir.join("main/lib.libsonnet"), "'main'").unwrap();
+        assert_eq!(
+            a.resolve(&base, "lib.libsonnet").unwrap().content,
+            b"'main'"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn share_contents() {
+        let dir =
+            std::env::temp_dir().join(format!("gojsonnet-cache-share-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.libsonnet").to_str().unwrap().to_owned();
+        let b = dir.join("b.libsonnet").to_str().unwrap().to_owned();
+
+        let cache = ImportCache::new();
+        let insert = |path: &str, content: &[u8]| {
+            std::fs::write(path, content).unwrap();
+            let stamp = Stamp::read(path).unwrap();
+            cache.insert(
+                path,
+                stamp,
+                &ImportedContent {
+                    found_here: path.to_owned(),
+                    content: content.to_vec(),
+                },
+            );
+            stamp
+        };
+        let (stamp_a, stamp_b) = (insert(&a, b"{}"), insert(&b, b"{}"));
+        assert!(std::sync::Arc::ptr_eq(
+            &cache.get(&a, stamp_a).unwrap(),
+            &cache.get(&b, stamp_b).unwrap()
+        ));
+        assert_eq!(cache.lock().contents.len(), 1);
+
+        insert(&b, b"[]");
+        insert(&a, b"[]");
+        // The unused content is forgotten
+        assert_eq!(cache.lock().contents.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changed_while_read() {
+        let dir =
+            std::env::temp_dir().join(format!("gojsonnet-cache-changed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.libsonnet");
+        std::fs::write(&path, "1").unwrap();
+        let base = format!("{}/", dir.to_str().unwrap());
+
+        // The file is rewritten right after the inner resolver reads it
+        struct RewritingImporter(crate::FileImporter);
+        impl ImportResolver for RewritingImporter {
+            fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+                let imported_content = self.0.resolve(base, rel)?;
+                std::fs::write(&imported_content.found_here, "22").unwrap();
+                Ok(imported_content)
+            }
+
+            fn locate(&self, base: &str, rel: &str) -> Located {
+                self.0.locate(base, rel)
+            }
+        }
+        let cache = std::sync::Arc::new(ImportCache::new());
+        let importer =
+            CachingImporter::new(RewritingImporter(crate::FileImporter::new()), cache.clone());
+        assert_eq!(
+            importer.resolve(&base, "a.libsonnet").unwrap().content,
+            b"1"
+        );
+        assert!(cache.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_files_are_not_cached() {
+        let mut inner = crate::MemoryImporter::new();
+        inner.add_file("a.libsonnet", "1");
+        let cache = std::sync::Arc::new(ImportCache::new());
+        let importer = CachingImporter::new(inner, cache.clone());
+        assert_eq!(importer.resolve("", "a.libsonnet").unwrap().content, b"1");
+        assert!(cache.is_empty());
+    }
+}
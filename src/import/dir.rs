@@ -0,0 +1,192 @@
+use super::{
+    candidate_paths, clean_path, ImportError, ImportLimits, ImportResolver, ImportedContent,
+    Located,
+};
+
+/// Import resolver which serves files of a directory under an import path prefix.
+///
+/// Files are reported with their import paths instead of the paths on the disk, so a directory
+/// mounted at `vendor` shadows files under `vendor/` in lower layers of `OverlayImporter`, and
+/// relative imports from the served files are resolved through the overlay again. Import paths
+/// are searched in the same order as `FileImporter`.
+///
+/// ```rust
+/// let mut patches = gojsonnet::DirImporter::new("testdata/lib");
+/// patches.mount("vendor");
+/// let mut overlay = gojsonnet::OverlayImporter::new();
+/// overlay.add_layer("patches", patches);
+/// overlay.add_layer("disk", gojsonnet::FileImporter::new());
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(overlay);
+/// let s: String = vm
+///     .evaluate_snippet(
+///         "dir_importer.jsonnet",
+///         "(import 'vendor/evaluate_file.libsonnet').name",
+///     )
+///     .unwrap();
+/// assert_eq!(s, "imported");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirImporter {
+    dir: std::path::PathBuf,
+    prefix: String,
+    jpaths: Vec<String>,
+}
+impl DirImporter {
+    /// Serve files of `dir`, mounted at the root of import paths.
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: Into<std::path::PathBuf>,
+    {
+        Self {
+            dir: dir.into(),
+            prefix: ".".to_owned(),
+            jpaths: Vec::new(),
+        }
+    }
+
+    /// Mount the directory under the given import path prefix.
+    pub fn mount<S>(&mut self, prefix: S)
+    where
+        S: Into<String>,
+    {
+        self.prefix = clean_path(&prefix.into());
+    }
+
+    /// Add a library search path. It is an import path, not a path on the disk.
+    pub fn jpath_add<S>(&mut self, path: S)
+    where
+        S: Into<String>,
+    {
+        self.jpaths.push(path.into());
+    }
+
+    /// Return the path on the disk of the import path.
+    fn disk_path(&self, path: &str) -> Option<String> {
+        let rest = if self.prefix == "." {
+            path
+        } else {
+            path.strip_prefix(&self.prefix)?.strip_prefix('/')?
+        };
+        // Ignore paths escaping from the directory
+        if rest == ".." || rest.starts_with("../") || rest.starts_with('/') {
+            return None;
+        }
+        Some(self.dir.join(rest).to_string_lossy().into_owned())
+    }
+
+    /// Read the first candidate file found with `read`.
+    fn find<F>(&self, base: &str, rel: &str, read: F) -> Result<ImportedContent, ImportError>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ImportError>,
+    {
+        for path in candidate_paths(base, rel, &self.jpaths) {
+            let disk_path = match self.disk_path(&path) {
+                Some(disk_path) => disk_path,
+                None => continue,
+            };
+            match read(&disk_path) {
+                Ok(content) => {
+                    return Ok(ImportedContent {
+                        found_here: path,
+                        content,
+                    })
+                }
+                Err(ImportError::IoError { inner, .. })
+                    if inner.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(ImportError::NotFound {
+            rel: rel.to_owned(),
+        })
+    }
+}
+impl ImportResolver for DirImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        self.find(base, rel, |path| {
+            std::fs::read(path).map_err(|inner| ImportError::IoError {
+                path: path.to_owned(),
+                inner,
+            })
+        })
+    }
+
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        self.find(base, rel, |path| limits.read_file(path))
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        for path in candidate_paths(base, rel, &self.jpaths) {
+            if let Some(disk_path) = self.disk_path(&path) {
+                match std::fs::metadata(&disk_path) {
+                    // found_here isn't the path on the disk
+                    Ok(_) => return Located::Unknown,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(_) => return Located::Unknown,
+                }
+            }
+        }
+        Located::NotFound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_vendor() {
+        let dir = std::env::temp_dir().join(format!("gojsonnet-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("patches/lib")).unwrap();
+        std::fs::write(dir.join("patches/lib/a.libsonnet"), "import 'b.libsonnet'").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let mut patches = DirImporter::new(dir.join("patches"));
+        patches.mount("vendor");
+        patches.jpath_add("vendor");
+        let mut vendor = crate::MemoryImporter::new();
+        vendor.add_file("vendor/lib/a.libsonnet", "'original'");
+        vendor.add_file("vendor/lib/b.libsonnet", "'original b'");
+        vendor.jpath_add("vendor");
+        let mut overlay = crate::OverlayImporter::new();
+        overlay.add_layer("patches", patches.clone());
+        overlay.add_layer("vendor", vendor);
+
+        for (base, rel) in &[("", "vendor/lib/a.libsonnet"), ("app/", "lib/a.libsonnet")] {
+            let imported_content = overlay.resolve(base, rel).unwrap();
+            assert_eq!(imported_content.found_here, "vendor/lib/a.libsonnet");
+            assert_eq!(imported_content.content, b"import 'b.libsonnet'");
+        }
+        // Relative imports from the patched file fall through to the lower layer
+        let imported_content = overlay.resolve("vendor/lib/", "b.libsonnet").unwrap();
+        assert_eq!(imported_content.content, b"'original b'");
+        assert_eq!(
+            overlay.layer_of("vendor/lib/b.libsonnet").as_deref(),
+            Some("vendor")
+        );
+
+        assert_eq!(
+            patches.locate("", "vendor/lib/a.libsonnet"),
+            Located::Unknown
+        );
+        assert_eq!(
+            patches.locate("", "vendor/lib/b.libsonnet"),
+            Located::NotFound
+        );
+        assert!(matches!(
+            patches.resolve("", "vendor/../secret.txt"),
+            Err(ImportError::NotFound { .. })
+        ));
+        assert!(matches!(
+            patches.resolve("vendor/", "../../secret.txt"),
+            Err(ImportError::NotFound { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+use super::{
+    join_path, FileImporter, ImportError, ImportLimits, ImportResolver, ImportedContent, Located,
+};
+
+/// Import resolver for projects managed by jsonnet-bundler (jb).
+///
+/// Imports are searched in the directory of the importing file, then in the `vendor` directory.
+/// Legacy short-name imports like `ksonnet-util/kausal.libsonnet` are resolved from
+/// `jsonnetfile.lock.json` (or `jsonnetfile.json`) without relying on the symlinks created by jb.
+///
+/// ```rust
+/// let importer = gojsonnet::JsonnetBundlerImporter::open("testdata/jb").unwrap();
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(importer);
+/// let v: serde_json::Value = vm
+///     .evaluate_snippet(
+///         "testdata/jb/main.jsonnet",
+///         "[(import 'ksonnet-util/kausal.libsonnet').name, (import 'github.com/grafana/jsonnet-libs/ksonnet-util/kausal.libsonnet').name]",
+///     )
+///     .unwrap();
+/// assert_eq!(v, serde_json::json!(["kausal", "kausal"]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonnetBundlerImporter {
+    vendor_dir: String,
+    legacy_imports: bool,
+    direct_dependencies: Vec<Dependency>,
+    locked_dependencies: Option<Vec<Dependency>>,
+    file_importer: FileImporter,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct JsonnetFile {
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+    #[serde(default = "default_legacy_imports", rename = "legacyImports")]
+    legacy_imports: bool,
+}
+fn default_legacy_imports() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct Dependency {
+    source: Source,
+    #[serde(default)]
+    sum: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct Source {
+    #[serde(default)]
+    git: Option<GitSource>,
+    #[serde(default)]
+    local: Option<LocalSource>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct GitSource {
+    remote: String,
+    #[serde(default)]
+    subdir: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct LocalSource {
+    directory: String,
+}
+
+impl Dependency {
+    /// Path of the dependency relative to the vendor directory.
+    fn vendor_path(&self) -> Option<String> {
+        if let Some(git) = &self.source.git {
+            let repo = git_remote_path(&git.remote);
+            let subdir = git.subdir.trim_matches('/');
+            if subdir.is_empty() {
+                Some(repo)
+            } else {
+                Some(format!("{}/{}", repo, subdir))
+            }
+        } else if let Some(local) = &self.source.local {
+            let directory = local.directory.trim_end_matches('/');
+            directory.rsplit('/').next().map(ToOwned::to_owned)
+        } else {
+            None
+        }
+    }
+
+    fn legacy_name(&self) -> Option<String> {
+        if let Some(name) = &self.name {
+            return Some(name.clone());
+        }
+        self.vendor_path()
+            .and_then(|path| path.rsplit('/').next().map(ToOwned::to_owned))
+    }
+}
+
+/// Convert a git remote like `https://github.com/grafana/jsonnet-libs.git` or
+/// `git@github.com:grafana/jsonnet-libs.git` to `github.com/grafana/jsonnet-libs`.
+fn git_remote_path(remote: &str) -> String {
+    let path = match remote.find("://") {
+        Some(i) => remote[i + 3..].to_owned(),
+        // scp-like syntax, e.g. git@github.com:grafana/jsonnet-libs.git
+        None => remote.replacen(':', "/", 1),
+    };
+    let (host, rest) = path.split_once('/').unwrap_or((&path, ""));
+    let host = host.rsplit('@').next().unwrap_or(host);
+    format!("{}/{}", host, rest)
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_owned()
+}
+
+/// Difference between `jsonnetfile.lock.json` and the vendor directory.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VendorMismatch {
+    /// The dependency in `jsonnetfile.json` is missing from `jsonnetfile.lock.json`.
+    #[error("{path} is not locked in jsonnetfile.lock.json")]
+    NotLocked { path: String },
+    /// The locked dependency is missing from the vendor directory.
+    #[error("{path} is not vendored")]
+    Missing { path: String },
+    /// The content of the vendored dependency doesn't match the checksum in the lock file.
+    #[error("checksum of {path} mismatched: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl JsonnetBundlerImporter {
+    /// Load `jsonnetfile.json` and `jsonnetfile.lock.json` from the project root.
+    ///
+    /// The lock file is optional.
+    pub fn open<P>(root: P) -> Result<Self, ImportError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let root = root.as_ref().to_string_lossy().into_owned();
+        let jsonnetfile: JsonnetFile = read_json(&join_path(&root, "jsonnetfile.json"))?;
+        let lock_path = join_path(&root, "jsonnetfile.lock.json");
+        let locked_dependencies = if std::path::Path::new(&lock_path).exists() {
+            let lockfile: JsonnetFile = read_json(&lock_path)?;
+            Some(lockfile.dependencies)
+        } else {
+            None
+        };
+        let vendor_dir = join_path(&root, "vendor");
+        let mut file_importer = FileImporter::new();
+        file_importer.jpath_add(vendor_dir.clone());
+        Ok(Self {
+            vendor_dir,
+            legacy_imports: jsonnetfile.legacy_imports,
+            direct_dependencies: jsonnetfile.dependencies,
+            locked_dependencies,
+            file_importer,
+        })
+    }
+
+    fn dependencies(&self) -> &[Dependency] {
+        self.locked_dependencies
+            .as_ref()
+            .unwrap_or(&self.direct_dependencies)
+    }
+
+    /// Check that the vendor directory matches `jsonnetfile.lock.json`.
+    ///
+    /// ```rust
+    /// let importer = gojsonnet::JsonnetBundlerImporter::open("testdata/jb").unwrap();
+    /// let mismatches = importer.verify().unwrap();
+    /// assert_eq!(
+    ///     mismatches,
+    ///     vec![gojsonnet::VendorMismatch::Missing {
+    ///         path: "github.com/jsonnet-libs/k8s-libsonnet/1.21".to_owned()
+    ///     }]
+    /// );
+    /// ```
+    pub fn verify(&self) -> Result<Vec<VendorMismatch>, ImportError> {
+        let mut mismatches = Vec::new();
+        let locked_dependencies = self.locked_dependencies.as_deref().unwrap_or_default();
+        let locked_paths: std::collections::HashSet<_> = locked_dependencies
+            .iter()
+            .filter_map(Dependency::vendor_path)
+            .collect();
+        for dependency in &self.direct_dependencies {
+            if let Some(path) = dependency.vendor_path() {
+                if !locked_paths.contains(&path) {
+                    mismatches.push(VendorMismatch::NotLocked { path });
+                }
+            }
+        }
+        for dependency in locked_dependencies {
+            let path = match dependency.vendor_path() {
+                Some(path) => path,
+                None => continue,
+            };
+            let dir = join_path(&self.vendor_dir, &path);
+            if !std::path::Path::new(&dir).is_dir() {
+                mismatches.push(VendorMismatch::Missing { path });
+                continue;
+            }
+            if dependency.sum.is_empty() {
+                continue;
+            }
+            let actual = hash_dir(&dir).map_err(|inner| ImportError::IoError {
+                path: dir.clone(),
+                inner,
+            })?;
+            if actual != dependency.sum {
+                mismatches.push(VendorMismatch::ChecksumMismatch {
+                    path,
+                    expected: dependency.sum.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Return the path of a legacy short-name import in the vendor directory.
+    fn legacy_path(&self, rel: &str) -> Option<String> {
+        let (name, rest) = rel.split_once('/')?;
+        let dependency = self
+            .dependencies()
+            .iter()
+            .find(|dependency| dependency.legacy_name().as_deref() == Some(name))?;
+        Some(join_path(
+            &join_path(&self.vendor_dir, &dependency.vendor_path()?),
+            rest,
+        ))
+    }
+
+    fn resolve_legacy<F>(
+        &self,
+        result: Result<ImportedContent, ImportError>,
+        read: F,
+    ) -> Result<ImportedContent, ImportError>
+    where
+        F: Fn(&str) -> Result<Vec<u8>, ImportError>,
+    {
+        let rel = match result {
+            Err(ImportError::NotFound { rel }) if self.legacy_imports => rel,
+            result => return result,
+        };
+        let path = match self.legacy_path(&rel) {
+            Some(path) => path,
+            None => return Err(ImportError::NotFound { rel }),
+        };
+        match read(&path) {
+            Ok(content) => Ok(ImportedContent {
+                found_here: path,
+                content,
+            }),
+            Err(ImportError::IoError { inner, .. })
+                if inner.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Err(ImportError::NotFound { rel })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl ImportResolver for JsonnetBundlerImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        self.resolve_legacy(self.file_importer.resolve(base, rel), |path| {
+            std::fs::read(path).map_err(|inner| ImportError::IoError {
+                path: path.to_owned(),
+                inner,
+            })
+        })
+    }
+
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        self.resolve_legacy(
+            self.file_importer.resolve_with_limits(base, rel, limits),
+            |path| limits.read_file(path),
+        )
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        match self.file_importer.locate(base, rel) {
+            Located::NotFound if self.legacy_imports => {
+                let path = match self.legacy_path(rel) {
+                    Some(path) => path,
+                    None => return Located::NotFound,
+                };
+                match std::fs::metadata(&path) {
+                    Ok(_) => Located::File(path),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Located::NotFound,
+                    Err(_) => Located::Unknown,
+                }
+            }
+            located => located,
+        }
+    }
+}
+
+fn read_json<T>(path: &str) -> Result<T, ImportError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let io_error = |inner| ImportError::IoError {
+        path: path.to_owned(),
+        inner,
+    };
+    let content = std::fs::read(path).map_err(io_error)?;
+    serde_json::from_slice(&content).map_err(|e| io_error(e.into()))
+}
+
+/// Compute the checksum of the directory in the same way as jb.
+fn hash_dir(dir: &str) -> std::io::Result<String> {
+    use sha2::Digest as _;
+
+    fn walk(
+        hasher: &mut sha2::Sha256,
+        root: &std::path::Path,
+        dir: &std::path::Path,
+    ) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                walk(hasher, root, &path)?;
+            } else {
+                let rel = path.strip_prefix(root).unwrap_or(&path);
+                hasher.update(rel.to_string_lossy().as_bytes());
+                hasher.update(std::fs::read(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    let mut hasher = sha2::Sha256::new();
+    let root = std::path::Path::new(dir);
+    walk(&mut hasher, root, root)?;
+    Ok(base64::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_remote_path() {
+        assert_eq!(
+            git_remote_path("https://github.com/grafana/jsonnet-libs.git"),
+            "github.com/grafana/jsonnet-libs"
+        );
+        assert_eq!(
+            git_remote_path("git@github.com:grafana/jsonnet-libs.git"),
+            "github.com/grafana/jsonnet-libs"
+        );
+        assert_eq!(
+            git_remote_path("ssh://git@github.com/grafana/jsonnet-libs"),
+            "github.com/grafana/jsonnet-libs"
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        let importer = JsonnetBundlerImporter::open("testdata/jb").unwrap();
+        let imported_content = importer
+            .resolve("", "ksonnet-util/kausal.libsonnet")
+            .unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "testdata/jb/vendor/github.com/grafana/jsonnet-libs/ksonnet-util/kausal.libsonnet"
+        );
+        let imported_content = importer
+            .resolve(
+                "testdata/jb/vendor/github.com/grafana/jsonnet-libs/ksonnet-util/",
+                "util.libsonnet",
+            )
+            .unwrap();
+        assert_eq!(
+            imported_content.found_here,
+            "testdata/jb/vendor/github.com/grafana/jsonnet-libs/ksonnet-util/util.libsonnet"
+        );
+        assert!(matches!(
+            importer.resolve("", "k8s-libsonnet/main.libsonnet"),
+            Err(ImportError::NotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+use super::{ImportError, ImportLimits, ImportResolver, ImportedContent, Located};
+
+/// Import resolver which stacks other resolvers. Earlier layers shadow later ones.
+///
+/// Each layer resolves imports by itself, and the next layer is tried only when the file is
+/// not found. A layer shadows a file of another layer by serving the same path, e.g. a
+/// `MemoryImporter` with `vendor/lib/a.libsonnet` over a `FileImporter`, or a `DirImporter`
+/// serving `patches/` mounted at `vendor`.
+///
+/// `locate` asks the layers in the same order, so files found on the disk by the top-most layer
+/// are cached when the overlay is wrapped in `CachingImporter`.
+///
+/// Clones share the record of which layer served each file, so keep a clone to query it after
+/// passing the importer to `Vm`.
+///
+/// ```rust
+/// let mut patches = gojsonnet::MemoryImporter::new();
+/// patches.add_file("testdata/lib/evaluate_file.libsonnet", "{ name: 'patched' }");
+/// let mut overlay = gojsonnet::OverlayImporter::new();
+/// overlay.add_layer("patches", patches);
+/// overlay.add_layer("disk", gojsonnet::FileImporter::new());
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_resolver(overlay.clone());
+/// let v: serde_json::Value = vm
+///     .evaluate_file("testdata/evaluate_file.jsonnet")
+///     .unwrap();
+/// assert_eq!(v["bar"], "patched");
+/// assert_eq!(
+///     overlay.layer_of("testdata/lib/evaluate_file.libsonnet").as_deref(),
+///     Some("patches")
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct OverlayImporter {
+    layers: Vec<(String, std::rc::Rc<dyn ImportResolver>)>,
+    served: std::rc::Rc<std::cell::RefCell<std::collections::HashMap<String, String>>>,
+}
+impl std::fmt::Debug for OverlayImporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverlayImporter")
+            .field(
+                "layers",
+                &self.layers.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("served", &self.served.borrow())
+            .finish()
+    }
+}
+impl OverlayImporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer below the existing layers.
+    pub fn add_layer<S, R>(&mut self, name: S, resolver: R)
+    where
+        S: Into<String>,
+        R: ImportResolver + 'static,
+    {
+        self.layers.push((name.into(), std::rc::Rc::new(resolver)));
+    }
+
+    /// Return the name of the layer which served the file.
+    pub fn layer_of(&self, found_here: &str) -> Option<String> {
+        self.served.borrow().get(found_here).cloned()
+    }
+
+    /// Return served files and the names of the layers, sorted by the path.
+    pub fn served_files(&self) -> Vec<(String, String)> {
+        let mut served: Vec<_> = self
+            .served
+            .borrow()
+            .iter()
+            .map(|(found_here, name)| (found_here.clone(), name.clone()))
+            .collect();
+        served.sort();
+        served
+    }
+
+    /// Resolve with the first layer which finds the file.
+    fn find<F>(&self, rel: &str, resolve: F) -> Result<ImportedContent, ImportError>
+    where
+        F: Fn(&dyn ImportResolver) -> Result<ImportedContent, ImportError>,
+    {
+        for (name, resolver) in &self.layers {
+            match resolve(resolver.as_ref()) {
+                Ok(imported_content) => {
+                    self.served
+                        .borrow_mut()
+                        .insert(imported_content.found_here.clone(), name.clone());
+                    return Ok(imported_content);
+                }
+                Err(ImportError::NotFound { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(ImportError::NotFound {
+            rel: rel.to_owned(),
+        })
+    }
+}
+impl ImportResolver for OverlayImporter {
+    fn resolve(&self, base: &str, rel: &str) -> Result<ImportedContent, ImportError> {
+        self.find(rel, |resolver| resolver.resolve(base, rel))
+    }
+
+    fn resolve_with_limits(
+        &self,
+        base: &str,
+        rel: &str,
+        limits: &ImportLimits<'_>,
+    ) -> Result<ImportedContent, ImportError> {
+        self.find(rel, |resolver| {
+            resolver.resolve_with_limits(base, rel, limits)
+        })
+    }
+
+    fn locate(&self, base: &str, rel: &str) -> Located {
+        for (name, resolver) in &self.layers {
+            match resolver.locate(base, rel) {
+                Located::File(found_here) => {
+                    // CachingImporter may serve the file without resolving it
+                    self.served
+                        .borrow_mut()
+                        .insert(found_here.clone(), name.clone());
+                    return Located::File(found_here);
+                }
+                Located::NotFound => {}
+                Located::Unknown => return Located::Unknown,
+            }
+        }
+        Located::NotFound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow() {
+        let mut generated = crate::MemoryImporter::new();
+        generated.add_file("vendor/generated.libsonnet", "{}");
+        let mut patches = crate::MemoryImporter::new();
+        patches.add_file("vendor/lib/a.libsonnet", "'patched'");
+        let mut vendor = crate::MemoryImporter::new();
+        vendor.add_file("vendor/lib/a.libsonnet", "'original'");
+        vendor.add_file("vendor/lib/b.libsonnet", "'original'");
+        vendor.jpath_add("vendor");
+
+        let mut overlay = OverlayImporter::new();
+        overlay.add_layer("generated", generated);
+        overlay.add_layer("patches", patches);
+        overlay.add_layer("vendor", vendor);
+
+        let imported_content = overlay.resolve("vendor/lib/", "a.libsonnet").unwrap();
+        assert_eq!(imported_content.content, b"'patched'");
+        let imported_content = overlay.resolve("", "lib/b.libsonnet").unwrap();
+        assert_eq!(imported_content.found_here, "vendor/lib/b.libsonnet");
+        overlay.resolve("", "vendor/generated.libsonnet").unwrap();
+        assert!(matches!(
+            overlay.resolve("", "c.libsonnet"),
+            Err(ImportError::NotFound { .. })
+        ));
+        assert_eq!(
+            overlay.served_files(),
+            vec![
+                (
+                    "vendor/generated.libsonnet".to_owned(),
+                    "generated".to_owned()
+                ),
+                ("vendor/lib/a.libsonnet".to_owned(), "patches".to_owned()),
+                ("vendor/lib/b.libsonnet".to_owned(), "vendor".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn locate() {
+        let dir =
+            std::env::temp_dir().join(format!("gojsonnet-overlay-locate-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("vendor")).unwrap();
+        std::fs::write(dir.join("vendor/a.libsonnet"), "'disk'").unwrap();
+        std::fs::write(dir.join("vendor/b.libsonnet"), "'disk'").unwrap();
+        let base = format!("{}/", dir.to_str().unwrap());
+
+        let mut patches = crate::MemoryImporter::new();
+        patches.add_file(&format!("{}vendor/a.libsonnet", base), "'patched'");
+        let mut overlay = OverlayImporter::new();
+        overlay.add_layer("patches", patches);
+        overlay.add_layer("disk", crate::FileImporter::new());
+
+        // The patched file isn't on the disk, so it must not be served from a cache
+        assert_eq!(
+            overlay.locate(&base, "vendor/a.libsonnet"),
+            Located::Unknown
+        );
+        assert_eq!(
+            overlay.locate(&base, "vendor/b.libsonnet"),
+            Located::File(format!("{}vendor/b.libsonnet", base))
+        );
+        assert_eq!(
+            overlay.locate(&base, "vendor/c.libsonnet"),
+            Located::NotFound
+        );
+
+        let cache = std::sync::Arc::new(crate::ImportCache::new());
+        let importer = crate::CachingImporter::new(overlay.clone(), cache.clone());
+        for _ in 0..2 {
+            let a = importer.resolve(&base, "vendor/a.libsonnet").unwrap();
+            assert_eq!(a.content, b"'patched'");
+            let b = importer.resolve(&base, "vendor/b.libsonnet").unwrap();
+            assert_eq!(b.content, b"'disk'");
+        }
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            overlay
+                .layer_of(&format!("{}vendor/b.libsonnet", base))
+                .as_deref(),
+            Some("disk")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stop_at_errors() {
+        let mut overlay = OverlayImporter::new();
+        overlay.add_layer(
+            "broken",
+            |_: &str, _: &str| -> Result<ImportedContent, ImportError> {
+                Err(ImportError::from("broken layer"))
+            },
+        );
+        overlay.add_layer("disk", crate::FileImporter::new());
+        let e = overlay
+            .resolve("testdata/", "evaluate_file.jsonnet")
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "broken layer");
+    }
+}
@@ -0,0 +1,434 @@
+use super::{clean_path, ImportError, ImportResolver, ImportedContent};
+
+/// Restrictions on files imported by Jsonnet code.
+///
+/// Violations are reported as Jsonnet runtime errors. Paths and sizes are checked before files
+/// are read by resolvers implementing `ImportResolver::resolve_with_limits`, like
+/// `FileImporter`; other resolvers are checked after they return the file.
+///
+/// ```rust
+/// let mut policy = gojsonnet::ImportPolicy::new();
+/// policy.allow_root("testdata");
+/// policy.deny_absolute_paths(true);
+/// policy.deny_parent_dirs(true);
+/// let mut vm = gojsonnet::Vm::default();
+/// vm.import_policy(policy);
+/// let v: serde_json::Value = vm
+///     .evaluate_file("testdata/evaluate_file.jsonnet")
+///     .unwrap();
+/// assert_eq!(v["bar"], "imported");
+/// let e = vm
+///     .evaluate_snippet::<String>("testdata/import_policy.jsonnet", "importstr '/etc/passwd'")
+///     .unwrap_err();
+/// assert!(
+///     e.to_string().contains("absolute paths are not allowed"),
+///     "{}",
+///     e
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ImportPolicy {
+    roots: Vec<String>,
+    deny_absolute_paths: bool,
+    deny_parent_dirs: bool,
+    max_file_size: Option<usize>,
+    max_imports: Option<usize>,
+    deny_importbin: bool,
+}
+impl ImportPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow importing files under the directory. When no roots are added, files are allowed
+    /// anywhere.
+    pub fn allow_root<S>(&mut self, path: S)
+    where
+        S: Into<String>,
+    {
+        self.roots.push(path.into());
+    }
+
+    /// Whether to reject imports of absolute paths like `/etc/passwd`.
+    pub fn deny_absolute_paths(&mut self, v: bool) {
+        self.deny_absolute_paths = v;
+    }
+
+    /// Whether to reject imports containing `..`.
+    pub fn deny_parent_dirs(&mut self, v: bool) {
+        self.deny_parent_dirs = v;
+    }
+
+    /// Set the maximum size of an imported file in bytes.
+    pub fn max_file_size(&mut self, n: usize) {
+        self.max_file_size = Some(n);
+    }
+
+    /// Set the maximum number of files imported in an evaluation.
+    pub fn max_imports(&mut self, n: usize) {
+        self.max_imports = Some(n);
+    }
+
+    /// Whether to reject `importbin`.
+    ///
+    /// go-jsonnet's C API doesn't tell import callbacks which kind of import they resolve, so
+    /// `importbin` is detected by scanning the evaluated code, code bound by `Vm::ext_code` and
+    /// `Vm::tla_code`, and imported files. Files evaluated by `Vm::evaluate_file` and its
+    /// variants are read once and evaluated as snippets, so the scanned code is the evaluated
+    /// code. A path imported by both `importbin` and `import` or `importstr` from the same
+    /// directory is rejected for all of them.
+    ///
+    /// ```rust
+    /// let mut policy = gojsonnet::ImportPolicy::new();
+    /// policy.deny_importbin(true);
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.import_policy(policy);
+    /// vm.ext_code("data", "importbin 'testdata/evaluate_file.jsonnet'")
+    ///     .unwrap();
+    /// let e = vm
+    ///     .evaluate_snippet::<serde_json::Value>("deny_importbin.jsonnet", "std.extVar('data')")
+    ///     .unwrap_err();
+    /// assert!(e.to_string().contains("importbin is not allowed"), "{}", e);
+    /// ```
+    pub fn deny_importbin(&mut self, v: bool) {
+        self.deny_importbin = v;
+    }
+}
+
+/// Applies `ImportPolicy` to imports of an evaluation.
+#[derive(Debug)]
+pub(crate) struct PolicyEnforcer {
+    policy: ImportPolicy,
+    imports: std::cell::Cell<usize>,
+    /// Pairs of the directory and the path of `importbin` found in evaluated files.
+    importbin_paths: std::cell::RefCell<std::collections::HashSet<(String, String)>>,
+}
+impl PolicyEnforcer {
+    pub(crate) fn new(policy: ImportPolicy) -> Self {
+        Self {
+            policy,
+            imports: std::cell::Cell::new(0),
+            importbin_paths: Default::default(),
+        }
+    }
+
+    /// Reset the state for an evaluation of `code`.
+    pub(crate) fn begin(&self, filename: &str, code: &str) {
+        self.imports.set(0);
+        self.importbin_paths.borrow_mut().clear();
+        self.scan(filename, code);
+    }
+
+    pub(crate) fn resolve(
+        &self,
+        resolver: &dyn ImportResolver,
+        base: &str,
+        rel: &str,
+    ) -> Result<ImportedContent, ImportError> {
+        let deny = |reason: &str| ImportError::PolicyViolation {
+            rel: rel.to_owned(),
+            reason: reason.to_owned(),
+        };
+        if self.policy.deny_absolute_paths && rel.starts_with('/') {
+            return Err(deny("absolute paths are not allowed"));
+        }
+        if self.policy.deny_parent_dirs && rel.split('/').any(|component| component == "..") {
+            return Err(deny("\"..\" is not allowed"));
+        }
+        if self.policy.deny_importbin
+            && self
+                .importbin_paths
+                .borrow()
+                .contains(&(base.to_owned(), rel.to_owned()))
+        {
+            return Err(deny("importbin is not allowed"));
+        }
+        let imports = self.imports.get() + 1;
+        self.imports.set(imports);
+        if let Some(max_imports) = self.policy.max_imports {
+            if imports > max_imports {
+                return Err(deny(&format!(
+                    "too many imports, the limit is {}",
+                    max_imports
+                )));
+            }
+        }
+
+        let limits = ImportLimits {
+            policy: &self.policy,
+            rel,
+        };
+        let imported_content = resolver.resolve_with_limits(base, rel, &limits)?;
+        self.scan(
+            &imported_content.found_here,
+            &String::from_utf8_lossy(&imported_content.content),
+        );
+        Ok(imported_content)
+    }
+
+    /// Record `importbin` in the code evaluated by go-jsonnet as `filename`.
+    pub(crate) fn scan(&self, filename: &str, code: &str) {
+        if !self.policy.deny_importbin {
+            return;
+        }
+        let base = crate::trace::dir_of(filename);
+        let mut importbin_paths = self.importbin_paths.borrow_mut();
+        for (kind, rel) in crate::trace::scan_imports(code) {
+            if kind == crate::ImportKind::ImportBin {
+                importbin_paths.insert((base.to_owned(), rel));
+            }
+        }
+    }
+}
+
+/// Limits of `ImportPolicy` on the file of an import, passed to
+/// `ImportResolver::resolve_with_limits`.
+///
+/// Resolvers reading files from the disk should read them with `read_file`, so that files
+/// outside of the allowed directories or larger than the limit are never read.
+#[derive(Debug)]
+pub struct ImportLimits<'a> {
+    policy: &'a ImportPolicy,
+    rel: &'a str,
+}
+impl ImportLimits<'_> {
+    /// Check the file resolved without limits.
+    pub fn check(&self, imported_content: &ImportedContent) -> Result<(), ImportError> {
+        if !self.is_allowed(&imported_content.found_here) {
+            return Err(self.outside_roots(&imported_content.found_here));
+        }
+        if let Some(max_file_size) = self.policy.max_file_size {
+            if imported_content.content.len() > max_file_size {
+                return Err(self.too_large(max_file_size));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the file on the disk.
+    ///
+    /// Symbolic links are resolved and the real path is checked against the allowed directories
+    /// before the file is opened, and at most the maximum file size is read. A missing file is
+    /// reported as `ImportError::IoError` with `std::io::ErrorKind::NotFound`.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, ImportError> {
+        use std::io::Read as _;
+
+        let io_error = |inner| ImportError::IoError {
+            path: path.to_owned(),
+            inner,
+        };
+        let real_path = if self.policy.roots.is_empty() {
+            std::path::PathBuf::from(path)
+        } else {
+            let real_path = std::fs::canonicalize(path).map_err(io_error)?;
+            if !self.is_allowed(&real_path.to_string_lossy()) {
+                return Err(self.outside_roots(path));
+            }
+            real_path
+        };
+        let mut file = std::fs::File::open(&real_path).map_err(io_error)?;
+        let mut content = Vec::new();
+        match self.policy.max_file_size {
+            Some(max_file_size) => {
+                file.take(max_file_size as u64 + 1)
+                    .read_to_end(&mut content)
+                    .map_err(io_error)?;
+                if content.len() > max_file_size {
+                    return Err(self.too_large(max_file_size));
+                }
+            }
+            None => {
+                file.read_to_end(&mut content).map_err(io_error)?;
+            }
+        }
+        Ok(content)
+    }
+
+    fn is_allowed(&self, path: &str) -> bool {
+        self.policy.roots.is_empty() || self.policy.roots.iter().any(|root| is_under(path, root))
+    }
+
+    fn outside_roots(&self, path: &str) -> ImportError {
+        ImportError::PolicyViolation {
+            rel: self.rel.to_owned(),
+            reason: format!("{} is outside of the allowed directories", path),
+        }
+    }
+
+    fn too_large(&self, max_file_size: usize) -> ImportError {
+        ImportError::PolicyViolation {
+            rel: self.rel.to_owned(),
+            reason: format!("the file is larger than {} bytes", max_file_size),
+        }
+    }
+}
+
+/// Whether the path is in the directory. Symbolic links are resolved when both exist on the
+/// disk.
+fn is_under(path: &str, root: &str) -> bool {
+    if let (Ok(path), Ok(root)) = (std::fs::canonicalize(path), std::fs::canonicalize(root)) {
+        return path.starts_with(root);
+    }
+    let path = clean_path(path);
+    let root = clean_path(root);
+    if root == "." {
+        !path.starts_with('/') && path != ".." && !path.starts_with("../")
+    } else if root == "/" {
+        path.starts_with('/')
+    } else {
+        path == root || path.starts_with(&format!("{}/", root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(enforcer: &PolicyEnforcer, base: &str, rel: &str) -> Result<String, String> {
+        let mut importer = crate::MemoryImporter::new();
+        importer.add_file("app/main.jsonnet", "{}");
+        importer.add_file("app/lib.libsonnet", "importbin 'data.bin'");
+        importer.add_file("app/data.bin", "data");
+        importer.add_file("secret/key.txt", "secret");
+        enforcer
+            .resolve(&importer, base, rel)
+            .map(|imported_content| imported_content.found_here)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_is_under() {
+        assert!(is_under("app/a.libsonnet", "app"));
+        assert!(is_under("app/a.libsonnet", "./app/"));
+        assert!(!is_under("app/../secret/key.txt", "app"));
+        assert!(!is_under("application/a.libsonnet", "app"));
+        assert!(is_under("a.libsonnet", "."));
+        assert!(!is_under("../a.libsonnet", "."));
+        assert!(!is_under("/etc/passwd", "."));
+        assert!(is_under("testdata/lib/evaluate_file.libsonnet", "testdata"));
+    }
+
+    #[test]
+    fn enforce_paths() {
+        let mut policy = ImportPolicy::new();
+        policy.allow_root("app");
+        policy.deny_absolute_paths(true);
+        let enforcer = PolicyEnforcer::new(policy);
+        enforcer.begin("app/main.jsonnet", "{}");
+        assert_eq!(
+            resolve(&enforcer, "app/", "lib.libsonnet"),
+            Ok("app/lib.libsonnet".to_owned())
+        );
+        assert_eq!(
+            resolve(&enforcer, "app/", "../secret/key.txt"),
+            Err("import \"../secret/key.txt\" denied by the import policy: secret/key.txt is outside of the allowed directories".to_owned())
+        );
+        assert_eq!(
+            resolve(&enforcer, "app/", "/etc/passwd"),
+            Err("import \"/etc/passwd\" denied by the import policy: absolute paths are not allowed".to_owned())
+        );
+
+        let mut policy = ImportPolicy::new();
+        policy.deny_parent_dirs(true);
+        let enforcer = PolicyEnforcer::new(policy);
+        assert_eq!(
+            resolve(&enforcer, "app/", "../secret/key.txt"),
+            Err(
+                "import \"../secret/key.txt\" denied by the import policy: \"..\" is not allowed"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let mut policy = ImportPolicy::new();
+        policy.max_imports(2);
+        policy.max_file_size(5);
+        let enforcer = PolicyEnforcer::new(policy);
+        enforcer.begin("app/main.jsonnet", "{}");
+        assert!(resolve(&enforcer, "app/", "data.bin").is_ok());
+        assert_eq!(
+            resolve(&enforcer, "", "secret/key.txt"),
+            Err("import \"secret/key.txt\" denied by the import policy: the file is larger than 5 bytes".to_owned())
+        );
+        assert_eq!(
+            resolve(&enforcer, "app/", "data.bin"),
+            Err(
+                "import \"data.bin\" denied by the import policy: too many imports, the limit is 2"
+                    .to_owned()
+            )
+        );
+        enforcer.begin("app/main.jsonnet", "{}");
+        assert!(resolve(&enforcer, "app/", "data.bin").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_file_limits() {
+        let dir = std::env::temp_dir().join(format!("gojsonnet-policy-{}", std::process::id()));
+        let app = dir.join("app");
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::write(app.join("a.libsonnet"), "{}").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), app.join("link.txt")).unwrap();
+        let app = app.to_str().unwrap();
+
+        let mut policy = ImportPolicy::new();
+        policy.allow_root(app);
+        policy.max_file_size(5);
+        let limits = ImportLimits {
+            policy: &policy,
+            rel: "x",
+        };
+        assert_eq!(
+            limits.read_file(&format!("{}/a.libsonnet", app)).unwrap(),
+            b"{}"
+        );
+        let e = limits
+            .read_file(&format!("{}/link.txt", app))
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("is outside of the allowed directories"), "{}", e);
+        assert!(matches!(
+            limits.read_file(&format!("{}/missing.txt", app)),
+            Err(ImportError::IoError { inner, .. }) if inner.kind() == std::io::ErrorKind::NotFound
+        ));
+
+        // Files are not read beyond the limit
+        let mut policy = ImportPolicy::new();
+        policy.max_file_size(5);
+        let limits = ImportLimits {
+            policy: &policy,
+            rel: "x",
+        };
+        assert_eq!(
+            limits.read_file("/dev/zero").unwrap_err().to_string(),
+            "import \"x\" denied by the import policy: the file is larger than 5 bytes"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn enforce_importbin() {
+        let mut policy = ImportPolicy::new();
+        policy.deny_importbin(true);
+        let enforcer = PolicyEnforcer::new(policy);
+        enforcer.begin("app/main.jsonnet", "import 'lib.libsonnet'");
+        assert!(resolve(&enforcer, "app/", "lib.libsonnet").is_ok());
+        assert_eq!(
+            resolve(&enforcer, "app/", "data.bin"),
+            Err(
+                "import \"data.bin\" denied by the import policy: importbin is not allowed"
+                    .to_owned()
+            )
+        );
+        enforcer.begin("app/main.jsonnet", "importstr 'data.bin'");
+        assert!(resolve(&enforcer, "app/", "data.bin").is_ok());
+
+        // Code of external variables is resolved from the current directory
+        enforcer.begin("app/main.jsonnet", "std.extVar('bin')");
+        enforcer.scan("<extvar:bin>", "importbin 'app/data.bin'");
+        assert!(resolve(&enforcer, "", "app/data.bin").is_err());
+    }
+}
@@ -1,15 +1,58 @@
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod error;
+mod fmt;
+mod import;
+mod trace;
+
+pub use error::{ErrorKind, JsonnetError, Location, Span, StackFrame};
+pub use fmt::{FmtCheck, FmtOptions, Formatter};
+#[cfg(feature = "fmt-dir")]
+pub use fmt::{FmtDirOptions, FmtMode, FmtSummary};
+#[cfg(feature = "archive")]
+pub use import::ArchiveImporter;
+pub use import::{
+    CachingImporter, DirImporter, FileImporter, ImportCache, ImportError, ImportLimits,
+    ImportPolicy, ImportResolver, ImportedContent, Located, MemoryImporter, OverlayImporter,
+};
+#[cfg(feature = "jsonnet-bundler")]
+pub use import::{JsonnetBundlerImporter, VendorMismatch};
+pub use trace::{ImportEdge, ImportGraph, ImportKind, Traced};
+
 /// Interpreter for Jsonnet.
 pub struct Vm {
     inner: *mut gojsonnet_sys::JsonnetVm,
     native_callback_holders: std::collections::HashMap<String, *mut NativeCallbackHolder>,
     import_callback_holder: Option<*mut ImportCallbackHolder>,
+    jpaths: Vec<String>,
+    /// Whether `FileImporter` is installed in place of go-jsonnet's default importer.
+    default_importer: bool,
+    import_policy: Option<std::rc::Rc<import::PolicyEnforcer>>,
+    /// Code bound by `ext_code` and `tla_code`, keyed by the file name go-jsonnet gives it.
+    code_vars: std::collections::BTreeMap<String, String>,
+    /// External variables bound by `ext_var` and `ext_code`.
+    ext_vars: std::collections::HashSet<String>,
+    /// External variables bound to the shims of `native_callback_json`.
+    json_shims: std::collections::HashSet<String>,
+    #[cfg(feature = "diagnostics")]
+    sources: std::rc::Rc<diagnostics::Sources>,
 }
 
+/// Error returned from this crate.
+///
+/// Some variants exist only with features like `fmt-toml` and `fmt-dir`, and Cargo enables
+/// features of a dependency for the whole build, so the enum is non-exhaustive.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Error returned from Jsonnet interpreter.
     #[error("go-jsonnet returned error: {message}")]
-    GoJsonnetError { message: String },
+    GoJsonnetError {
+        /// Raw error message formatted by go-jsonnet.
+        message: String,
+        /// Structured error parsed from the message.
+        details: Box<JsonnetError>,
+    },
     /// Error while converting Rust string to C string.
     #[error("C-string nul error: {inner}")]
     NulError {
@@ -22,15 +65,59 @@ pub enum Error {
         #[from]
         inner: serde_json::Error,
     },
+    /// Error while converting a path which cannot be passed to go-jsonnet.
+    #[error("Invalid path: {}", path.display())]
+    InvalidPath { path: std::path::PathBuf },
+    /// Error while binding an external variable used by another binding.
+    #[error("External variable {name} is already bound by {bound_by}")]
+    ExtVarConflict {
+        name: String,
+        bound_by: &'static str,
+    },
+    /// Error while registering a native function with a parameter name which can't be used in
+    /// Jsonnet code.
+    #[error("Invalid parameter name: {param:?}")]
+    InvalidParameter { param: String },
+    /// Error while reading or writing a file.
+    #[error("I/O error on {}: {inner}", path.display())]
+    IoError {
+        path: std::path::PathBuf,
+        #[source]
+        inner: std::io::Error,
+    },
+    /// Error while deserializing TOML.
+    #[cfg(feature = "fmt-toml")]
+    #[error("TOML error: {inner}")]
+    TomlError {
+        #[from]
+        inner: toml::de::Error,
+    },
+    /// Error while parsing a glob pattern.
+    #[cfg(feature = "fmt-dir")]
+    #[error("Invalid pattern {pattern:?}: {inner}")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        inner: ignore::Error,
+    },
+}
+impl Error {
+    fn go_jsonnet(message: String) -> Self {
+        let details = Box::new(JsonnetError::parse(&message));
+        Self::GoJsonnetError { message, details }
+    }
 }
 
-pub type NativeCallback = fn(argv: Vec<serde_json::Value>) -> Option<serde_json::Value>;
+pub type NativeCallback = Box<dyn Fn(&[serde_json::Value]) -> Result<serde_json::Value, String>>;
 
 #[repr(C)]
 struct NativeCallbackHolder {
     vm: *mut gojsonnet_sys::JsonnetVm,
+    name: String,
     callback: NativeCallback,
     argc: usize,
+    /// Whether arguments are passed as JSON strings by the shim of `native_callback_json`.
+    json_args: bool,
 }
 unsafe extern "C" fn native_callback_bridge(
     ctx: *mut std::ffi::c_void,
@@ -39,95 +126,170 @@ unsafe extern "C" fn native_callback_bridge(
 ) -> *mut gojsonnet_sys::JsonnetJsonValue {
     let holder = ctx as *const NativeCallbackHolder;
     let vm = (*holder).vm;
-    let callback = (*holder).callback;
-    let argc = (*holder).argc;
-    let mut argv = Vec::with_capacity(argc);
-    for i in 0..argc {
-        argv.push(from_gojsonnet_value(vm, *argv_c.offset(i as isize)));
-    }
-    if let Some(result) = callback(argv) {
-        *success = 1;
-        from_serde_json_value(vm, result)
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        call_native_callback(&*holder, argv_c)
+    }))
+    .unwrap_or_else(|payload| {
+        Err(format!(
+            "native callback panicked: {}",
+            panic_message(&*payload)
+        ))
+    });
+    match result {
+        Ok(value) => {
+            *success = 1;
+            value
+        }
+        Err(e) => {
+            *success = 0;
+            let message = format!("{}: {}", (*holder).name, e).replace('\0', "\\0");
+            let message_cstr = std::ffi::CString::new(message).unwrap_or_default();
+            gojsonnet_sys::jsonnet_json_make_string(vm, message_cstr.as_ptr())
+        }
+    }
+}
+unsafe fn call_native_callback(
+    holder: &NativeCallbackHolder,
+    argv_c: *const *const gojsonnet_sys::JsonnetJsonValue,
+) -> Result<*mut gojsonnet_sys::JsonnetJsonValue, String> {
+    let mut argv = Vec::with_capacity(holder.argc);
+    for i in 0..holder.argc {
+        let arg = from_gojsonnet_value(holder.vm, *argv_c.add(i));
+        if holder.json_args {
+            match arg {
+                Ok(serde_json::Value::String(json_str)) => {
+                    argv.push(serde_json::from_str(&json_str).map_err(|e| e.to_string())?)
+                }
+                _ => {
+                    return Err(format!(
+                        "argument {} is not a JSON string, call std.extVar('{}') to pass any value",
+                        i, holder.name
+                    ))
+                }
+            }
+        } else {
+            argv.push(arg?);
+        }
+    }
+    let result = (holder.callback)(&argv)?;
+    from_serde_json_value(holder.vm, result)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
     } else {
-        gojsonnet_sys::jsonnet_json_make_null(vm)
+        "Box<dyn Any>"
     }
 }
 
+/// Return `true` when `s` is a Jsonnet identifier.
+fn is_identifier(s: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "assert",
+        "else",
+        "error",
+        "false",
+        "for",
+        "function",
+        "if",
+        "import",
+        "importbin",
+        "importstr",
+        "in",
+        "local",
+        "null",
+        "self",
+        "super",
+        "tailstrict",
+        "then",
+        "true",
+    ];
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&s)
+}
+
 unsafe fn from_serde_json_value(
     vm: *mut gojsonnet_sys::JsonnetVm,
     value: serde_json::Value,
-) -> *mut gojsonnet_sys::JsonnetJsonValue {
-    match value {
+) -> Result<*mut gojsonnet_sys::JsonnetJsonValue, String> {
+    Ok(match value {
         serde_json::Value::Null => gojsonnet_sys::jsonnet_json_make_null(vm),
         serde_json::Value::Bool(b) => gojsonnet_sys::jsonnet_json_make_bool(vm, b.into()),
-        serde_json::Value::Number(n) => {
-            gojsonnet_sys::jsonnet_json_make_number(vm, n.as_f64().unwrap())
-        }
+        serde_json::Value::Number(n) => match n.as_f64() {
+            Some(f) => gojsonnet_sys::jsonnet_json_make_number(vm, f),
+            None => return Err(format!("cannot convert {} to a Jsonnet number", n)),
+        },
         serde_json::Value::String(s) => {
-            gojsonnet_sys::jsonnet_json_make_string(vm, std::ffi::CString::new(s).unwrap().as_ptr())
+            let s_cstr = std::ffi::CString::new(s).map_err(|e| e.to_string())?;
+            gojsonnet_sys::jsonnet_json_make_string(vm, s_cstr.as_ptr())
         }
         serde_json::Value::Array(v) => {
             let ary = gojsonnet_sys::jsonnet_json_make_array(vm);
             for e in v {
-                gojsonnet_sys::jsonnet_json_array_append(vm, ary, from_serde_json_value(vm, e));
+                gojsonnet_sys::jsonnet_json_array_append(vm, ary, from_serde_json_value(vm, e)?);
             }
             ary
         }
         serde_json::Value::Object(m) => {
             let obj = gojsonnet_sys::jsonnet_json_make_object(vm);
             for (k, v) in m {
+                let k_cstr = std::ffi::CString::new(k).map_err(|e| e.to_string())?;
                 gojsonnet_sys::jsonnet_json_object_append(
                     vm,
                     obj,
-                    std::ffi::CString::new(k).unwrap().as_ptr(),
-                    from_serde_json_value(vm, v),
+                    k_cstr.as_ptr(),
+                    from_serde_json_value(vm, v)?,
                 );
             }
             obj
         }
-    }
+    })
 }
 
 unsafe fn from_gojsonnet_value(
     vm: *mut gojsonnet_sys::JsonnetVm,
     value: *const gojsonnet_sys::JsonnetJsonValue,
-) -> serde_json::Value {
+) -> Result<serde_json::Value, String> {
     if gojsonnet_sys::jsonnet_json_extract_null(vm, value) != 0 {
-        return serde_json::Value::Null;
+        return Ok(serde_json::Value::Null);
     }
     let b = gojsonnet_sys::jsonnet_json_extract_bool(vm, value);
     if b == 0 {
-        return serde_json::Value::Bool(false);
+        return Ok(serde_json::Value::Bool(false));
     } else if b == 1 {
-        return serde_json::Value::Bool(true);
+        return Ok(serde_json::Value::Bool(true));
     }
     let mut n = 0.0;
     if gojsonnet_sys::jsonnet_json_extract_number(vm, value, &mut n) != 0 {
-        return serde_json::Value::Number(serde_json::Number::from_f64(n).unwrap());
+        return match serde_json::Number::from_f64(n) {
+            Some(n) => Ok(serde_json::Value::Number(n)),
+            None => Err(format!("cannot convert {} to a JSON number", n)),
+        };
     }
     let c_str = gojsonnet_sys::jsonnet_json_extract_string(vm, value);
     if !c_str.is_null() {
-        let s = std::ffi::CStr::from_ptr(c_str).to_str().unwrap().to_owned();
-        return serde_json::Value::String(s);
+        let s = std::ffi::CStr::from_ptr(c_str)
+            .to_string_lossy()
+            .into_owned();
+        return Ok(serde_json::Value::String(s));
     }
-    // XXX: array and object?
-
-    panic!("Unsupported value: {:?}", value);
-}
-
-/// Result of the imported content.
-pub struct ImportedContent {
-    /// Path to the imported file, absolute or relative to the process's CWD.
-    pub found_here: String,
-    /// Content of the imported file
-    pub content: String,
+    // go-jsonnet's C API doesn't provide a way to extract arrays and objects
+    Err("array and object arguments are not supported by go-jsonnet C API, register the function with native_callback_json instead".to_owned())
 }
-pub type ImportCallback = fn(base: &str, base: &str) -> Result<ImportedContent, String>;
 
 #[repr(C)]
 struct ImportCallbackHolder {
     vm: *mut gojsonnet_sys::JsonnetVm,
-    callback: ImportCallback,
+    resolver: Box<dyn ImportResolver>,
+    trace: std::cell::RefCell<Option<Vec<trace::ResolvedImport>>>,
+    policy: Option<std::rc::Rc<import::PolicyEnforcer>>,
+    #[cfg(feature = "diagnostics")]
+    sources: std::rc::Rc<diagnostics::Sources>,
 }
 unsafe extern "C" fn import_callback_bridge(
     ctx: *mut std::ffi::c_void,
@@ -138,15 +300,50 @@ unsafe extern "C" fn import_callback_bridge(
 ) -> *mut std::os::raw::c_char {
     let holder = ctx as *const ImportCallbackHolder;
     let vm = (*holder).vm;
-    let callback = (*holder).callback;
+    let resolver = &(*holder).resolver;
     let base = std::ffi::CStr::from_ptr(base).to_string_lossy();
     let rel = std::ffi::CStr::from_ptr(rel).to_string_lossy();
-    use std::borrow::Borrow as _;
-    match callback(base.borrow(), rel.borrow()) {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match &(*holder).policy {
+            Some(policy) => policy.resolve(resolver.as_ref(), &base, &rel),
+            None => resolver.resolve(&base, &rel),
+        }
+        .map_err(|e| e.to_string())
+    }))
+    .unwrap_or_else(|payload| {
+        Err(format!(
+            "import callback panicked: {}",
+            panic_message(&*payload)
+        ))
+    })
+    .and_then(|imported_content| {
+        // go-jsonnet's C API takes the content as a NUL-terminated string
+        if imported_content.content.contains(&0) {
+            Err(format!(
+                "{} contains NUL bytes, which can't be passed through the import callback",
+                imported_content.found_here
+            ))
+        } else {
+            Ok(imported_content)
+        }
+    });
+    match result {
         Ok(imported_content) => {
+            #[cfg(feature = "diagnostics")]
+            (*holder)
+                .sources
+                .insert_import(&imported_content.found_here, &base, &rel);
+            if let Some(resolved) = (*holder).trace.borrow_mut().as_mut() {
+                resolved.push(trace::ResolvedImport {
+                    base: base.into_owned(),
+                    rel: rel.into_owned(),
+                    found_here: imported_content.found_here.clone(),
+                    content: String::from_utf8_lossy(&imported_content.content).into_owned(),
+                });
+            }
             *success = 1;
             *found_here = to_jsonnet_str(vm, &imported_content.found_here);
-            to_jsonnet_str(vm, &imported_content.content)
+            to_jsonnet_bytes(vm, &imported_content.content)
         }
         Err(e) => {
             *success = 0;
@@ -158,20 +355,123 @@ unsafe fn to_jsonnet_str(
     vm: *mut gojsonnet_sys::JsonnetVm,
     rust_str: &str,
 ) -> *mut std::os::raw::c_char {
-    let dst = gojsonnet_sys::jsonnet_realloc(vm, std::ptr::null_mut(), rust_str.len() as u64 + 1);
-    std::ptr::copy_nonoverlapping(rust_str.as_ptr(), dst as *mut u8, rust_str.len());
-    *dst.offset(rust_str.len() as isize) = 0;
+    to_jsonnet_bytes(vm, rust_str.as_bytes())
+}
+unsafe fn to_jsonnet_bytes(
+    vm: *mut gojsonnet_sys::JsonnetVm,
+    bytes: &[u8],
+) -> *mut std::os::raw::c_char {
+    let dst = gojsonnet_sys::jsonnet_realloc(vm, std::ptr::null_mut(), bytes.len() as u64 + 1);
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst as *mut u8, bytes.len());
+    *dst.add(bytes.len()) = 0;
     dst
 }
 
+/// Take the ownership of a string returned from go-jsonnet.
+unsafe fn take_output(
+    vm: *mut gojsonnet_sys::JsonnetVm,
+    ptr: *mut std::os::raw::c_char,
+    err: std::os::raw::c_int,
+) -> Result<String, Error> {
+    let s = std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    gojsonnet_sys::jsonnet_realloc(vm, ptr, 0);
+    if err == 0 {
+        Ok(s)
+    } else {
+        Err(Error::go_jsonnet(s))
+    }
+}
+
+#[cfg(unix)]
+fn path_to_cstring(path: &std::path::Path) -> Result<std::ffi::CString, Error> {
+    use std::os::unix::ffi::OsStrExt as _;
+    Ok(std::ffi::CString::new(path.as_os_str().as_bytes())?)
+}
+#[cfg(not(unix))]
+fn path_to_cstring(path: &std::path::Path) -> Result<std::ffi::CString, Error> {
+    match path.to_str() {
+        Some(s) => Ok(std::ffi::CString::new(s)?),
+        None => Err(Error::InvalidPath {
+            path: path.to_owned(),
+        }),
+    }
+}
+
+/// Read a file to evaluate it as a snippet, so that `ImportPolicy` checks the same code that
+/// go-jsonnet evaluates.
+fn read_source(path: &std::path::Path) -> Result<(&str, String), Error> {
+    let filename = path.to_str().ok_or_else(|| Error::InvalidPath {
+        path: path.to_owned(),
+    })?;
+    let code = std::fs::read_to_string(path).map_err(|inner| Error::IoError {
+        path: path.to_owned(),
+        inner,
+    })?;
+    Ok((filename, code))
+}
+
+/// Pair file names and their contents returned from `jsonnet_evaluate_*_multi`.
+fn pair_multi(outputs: Vec<String>) -> std::collections::BTreeMap<String, String> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut it = outputs.into_iter();
+    while let (Some(filename), Some(content)) = (it.next(), it.next()) {
+        files.insert(filename, content);
+    }
+    files
+}
+
+fn deserialize_multi<T>(
+    files: std::collections::BTreeMap<String, String>,
+) -> Result<std::collections::BTreeMap<String, T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut values = std::collections::BTreeMap::new();
+    for (filename, json_str) in files {
+        values.insert(filename, serde_json::from_str(&json_str)?);
+    }
+    Ok(values)
+}
+
+fn deserialize_stream<T>(outputs: Vec<String>) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut docs = Vec::with_capacity(outputs.len());
+    for json_str in outputs {
+        docs.push(serde_json::from_str(&json_str)?);
+    }
+    Ok(docs)
+}
+
+/// Render documents in the same way as `jsonnet -y`.
+fn render_yaml_stream(outputs: Vec<String>) -> String {
+    let mut yaml = String::new();
+    for json_str in &outputs {
+        yaml.push_str("---\n");
+        yaml.push_str(json_str);
+        if !json_str.ends_with('\n') {
+            yaml.push('\n');
+        }
+    }
+    if !outputs.is_empty() {
+        yaml.push_str("...\n");
+    }
+    yaml
+}
+
 /// Preferred style for string literals.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum StringStyle {
     /// Prefer double quotes (")
+    #[serde(alias = "d")]
     Double,
     /// Prefer signel quotes (')
+    #[serde(alias = "s")]
     Single,
     /// Do not change string literals
+    #[serde(alias = "l")]
     Leave,
 }
 impl StringStyle {
@@ -185,13 +485,17 @@ impl StringStyle {
 }
 
 /// Preferred style for comments.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CommentStyle {
     /// Prefer hash (#)
+    #[serde(alias = "h")]
     Hash,
     /// Prefer slash (//)
+    #[serde(alias = "s")]
     Slash,
     /// Do not change comments
+    #[serde(alias = "l")]
     Leave,
 }
 impl CommentStyle {
@@ -211,6 +515,14 @@ impl Vm {
             inner: unsafe { gojsonnet_sys::jsonnet_make() },
             native_callback_holders: std::collections::HashMap::new(),
             import_callback_holder: None,
+            jpaths: Vec::new(),
+            default_importer: false,
+            import_policy: None,
+            code_vars: std::collections::BTreeMap::new(),
+            ext_vars: std::collections::HashSet::new(),
+            json_shims: std::collections::HashSet::new(),
+            #[cfg(feature = "diagnostics")]
+            sources: Default::default(),
         }
     }
 
@@ -230,6 +542,30 @@ impl Vm {
         unsafe { gojsonnet_sys::jsonnet_max_stack(self.inner, v) };
     }
 
+    /// Set the maximum number of stack frames shown in error messages. 0 shows all frames.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.max_trace(0);
+    /// ```
+    pub fn max_trace(&mut self, v: u32) {
+        unsafe { gojsonnet_sys::jsonnet_max_trace(self.inner, v) };
+    }
+
+    /// Whether to expect a string as output and write it as is instead of JSON.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.string_output(true);
+    /// let s = vm
+    ///     .evaluate_snippet_raw("string_output.jsonnet", "'hello ' + 'world'")
+    ///     .unwrap();
+    /// assert_eq!(s, "hello world\n");
+    /// ```
+    pub fn string_output(&mut self, v: bool) {
+        unsafe { gojsonnet_sys::jsonnet_string_output(self.inner, v as i32) };
+    }
+
     /// Evaluate a Jsonnet code and return a JSON string.
     ///
     /// ```rust
@@ -251,36 +587,561 @@ impl Vm {
     where
         T: serde::de::DeserializeOwned,
     {
+        let json_str = self.evaluate_snippet_raw(filename, code)?;
+        Ok(serde_json::from_str(&json_str)?)
+    }
+
+    /// Evaluate a Jsonnet code and return the output of the interpreter as is.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.string_output(true);
+    /// let ini = vm
+    ///     .evaluate_snippet_raw(
+    ///         "evaluate_snippet_raw.jsonnet",
+    ///         "std.manifestIni({sections: {main: {key: 'value'}}})",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(ini, "[main]\nkey = value\n\n");
+    /// ```
+    pub fn evaluate_snippet_raw(&self, filename: &str, code: &str) -> Result<String, Error> {
         let filename_cstr = std::ffi::CString::new(filename)?;
         let code_cstr = std::ffi::CString::new(code)?;
+        self.begin_evaluation(filename, Some(code));
         let mut err = 0;
         unsafe {
             let ptr = gojsonnet_sys::jsonnet_evaluate_snippet(
                 self.inner,
-                filename_cstr.as_ptr(),
-                code_cstr.as_ptr(),
+                filename_cstr.as_ptr(),
+                code_cstr.as_ptr(),
+                &mut err,
+            );
+            self.take_output(ptr, err)
+        }
+    }
+
+    /// Evaluate a Jsonnet file.
+    ///
+    /// Imports are resolved relative to the directory of the given file.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// #[derive(Debug, PartialEq, serde::Deserialize)]
+    /// struct S {
+    ///     foo: i32,
+    ///     bar: String,
+    /// }
+    /// let s: S = vm.evaluate_file("testdata/evaluate_file.jsonnet").unwrap();
+    /// assert_eq!(
+    ///     s,
+    ///     S {
+    ///         foo: 3,
+    ///         bar: "imported".to_owned()
+    ///     }
+    /// );
+    /// ```
+    pub fn evaluate_file<T, P>(&self, path: P) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        P: AsRef<std::path::Path>,
+    {
+        let json_str = self.evaluate_file_raw(path)?;
+        Ok(serde_json::from_str(&json_str)?)
+    }
+
+    /// Evaluate a Jsonnet file and return a JSON string.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let json_str = vm
+    ///     .evaluate_file_raw("testdata/evaluate_file.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(json_str, "{\n   \"bar\": \"imported\",\n   \"foo\": 3\n}\n");
+    /// ```
+    pub fn evaluate_file_raw<P>(&self, path: P) -> Result<String, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        if self.import_policy.is_some() {
+            let (filename, code) = read_source(path.as_ref())?;
+            return self.evaluate_snippet_raw(filename, &code);
+        }
+        let path_cstr = path_to_cstring(path.as_ref())?;
+        self.begin_evaluation(&path.as_ref().to_string_lossy(), None);
+        let mut err = 0;
+        unsafe {
+            let ptr =
+                gojsonnet_sys::jsonnet_evaluate_file(self.inner, path_cstr.as_ptr(), &mut err);
+            self.take_output(ptr, err)
+        }
+    }
+
+    /// Evaluate a Jsonnet code which returns an object of multiple files.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let files: std::collections::BTreeMap<String, Vec<i32>> = vm
+    ///     .evaluate_snippet_multi(
+    ///         "evaluate_snippet_multi.jsonnet",
+    ///         "{'a.json': [1, 2], 'b.json': [3]}",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(files.len(), 2);
+    /// assert_eq!(files["a.json"], vec![1, 2]);
+    /// assert_eq!(files["b.json"], vec![3]);
+    /// ```
+    pub fn evaluate_snippet_multi<T>(
+        &self,
+        filename: &str,
+        code: &str,
+    ) -> Result<std::collections::BTreeMap<String, T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        deserialize_multi(self.evaluate_snippet_multi_raw(filename, code)?)
+    }
+
+    /// Evaluate a Jsonnet code which returns an object of multiple files and return the output
+    /// of the interpreter for each file as is.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.string_output(true);
+    /// let files = vm
+    ///     .evaluate_snippet_multi_raw(
+    ///         "evaluate_snippet_multi_raw.jsonnet",
+    ///         "{'a.txt': 'hello', 'b.txt': 'world'}",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(files["a.txt"], "hello\n");
+    /// assert_eq!(files["b.txt"], "world\n");
+    /// ```
+    pub fn evaluate_snippet_multi_raw(
+        &self,
+        filename: &str,
+        code: &str,
+    ) -> Result<std::collections::BTreeMap<String, String>, Error> {
+        let filename_cstr = std::ffi::CString::new(filename)?;
+        let code_cstr = std::ffi::CString::new(code)?;
+        self.begin_evaluation(filename, Some(code));
+        let mut err = 0;
+        let outputs = unsafe {
+            let ptr = gojsonnet_sys::jsonnet_evaluate_snippet_multi(
+                self.inner,
+                filename_cstr.as_ptr(),
+                code_cstr.as_ptr(),
+                &mut err,
+            );
+            self.take_output_list(ptr, err)?
+        };
+        Ok(pair_multi(outputs))
+    }
+
+    /// Evaluate a Jsonnet file which returns an object of multiple files.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let files: std::collections::BTreeMap<String, serde_json::Value> = vm
+    ///     .evaluate_file_multi("testdata/evaluate_file_multi.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     files.keys().collect::<Vec<_>>(),
+    ///     vec!["deployment.json", "service.json"]
+    /// );
+    /// assert_eq!(files["service.json"]["kind"], "Service");
+    /// ```
+    pub fn evaluate_file_multi<T, P>(
+        &self,
+        path: P,
+    ) -> Result<std::collections::BTreeMap<String, T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        P: AsRef<std::path::Path>,
+    {
+        deserialize_multi(self.evaluate_file_multi_raw(path)?)
+    }
+
+    /// Evaluate a Jsonnet file which returns an object of multiple files and return the output
+    /// of the interpreter for each file as is.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let files = vm
+    ///     .evaluate_file_multi_raw("testdata/evaluate_file_multi.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     files["service.json"],
+    ///     "{\n   \"apiVersion\": \"v1\",\n   \"kind\": \"Service\"\n}\n"
+    /// );
+    /// ```
+    pub fn evaluate_file_multi_raw<P>(
+        &self,
+        path: P,
+    ) -> Result<std::collections::BTreeMap<String, String>, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        if self.import_policy.is_some() {
+            let (filename, code) = read_source(path.as_ref())?;
+            return self.evaluate_snippet_multi_raw(filename, &code);
+        }
+        let path_cstr = path_to_cstring(path.as_ref())?;
+        self.begin_evaluation(&path.as_ref().to_string_lossy(), None);
+        let mut err = 0;
+        let outputs = unsafe {
+            let ptr = gojsonnet_sys::jsonnet_evaluate_file_multi(
+                self.inner,
+                path_cstr.as_ptr(),
+                &mut err,
+            );
+            self.take_output_list(ptr, err)?
+        };
+        Ok(pair_multi(outputs))
+    }
+
+    /// Evaluate a Jsonnet code which returns an array of documents.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let docs: Vec<serde_json::Value> = vm
+    ///     .evaluate_snippet_stream(
+    ///         "evaluate_snippet_stream.jsonnet",
+    ///         "[{kind: 'Service'}, {kind: 'Deployment'}]",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     docs,
+    ///     vec![
+    ///         serde_json::json!({"kind": "Service"}),
+    ///         serde_json::json!({"kind": "Deployment"}),
+    ///     ]
+    /// );
+    /// ```
+    pub fn evaluate_snippet_stream<T>(&self, filename: &str, code: &str) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        deserialize_stream(self.evaluate_snippet_stream_list(filename, code)?)
+    }
+
+    /// Evaluate a Jsonnet file which returns an array of documents.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let docs: Vec<serde_json::Value> = vm
+    ///     .evaluate_file_stream("testdata/evaluate_file_stream.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(docs.len(), 2);
+    /// assert_eq!(docs[0]["kind"], "Service");
+    /// assert_eq!(docs[1]["kind"], "Deployment");
+    /// ```
+    pub fn evaluate_file_stream<T, P>(&self, path: P) -> Result<Vec<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        P: AsRef<std::path::Path>,
+    {
+        deserialize_stream(self.evaluate_file_stream_list(path.as_ref())?)
+    }
+
+    /// Evaluate a Jsonnet code which returns an array of documents and render them as a YAML
+    /// stream.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let yaml = vm
+    ///     .evaluate_snippet_yaml_stream("evaluate_snippet_yaml_stream.jsonnet", "[1, 'a']")
+    ///     .unwrap();
+    /// assert_eq!(yaml, "---\n1\n---\n\"a\"\n...\n");
+    /// ```
+    pub fn evaluate_snippet_yaml_stream(
+        &self,
+        filename: &str,
+        code: &str,
+    ) -> Result<String, Error> {
+        Ok(render_yaml_stream(
+            self.evaluate_snippet_stream_list(filename, code)?,
+        ))
+    }
+
+    /// Evaluate a Jsonnet file which returns an array of documents and render them as a YAML
+    /// stream.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let yaml = vm
+    ///     .evaluate_file_yaml_stream("testdata/evaluate_file_stream.jsonnet")
+    ///     .unwrap();
+    /// assert!(yaml.starts_with("---\n{\n   \"apiVersion\": \"v1\",\n"));
+    /// assert!(yaml.ends_with("}\n...\n"));
+    /// ```
+    pub fn evaluate_file_yaml_stream<P>(&self, path: P) -> Result<String, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Ok(render_yaml_stream(
+            self.evaluate_file_stream_list(path.as_ref())?,
+        ))
+    }
+
+    /// Evaluate a Jsonnet code and return the result with the files it imported.
+    ///
+    /// When an import resolver is set, imports are recorded through the import callback. Each
+    /// traced evaluation registers the callback again, which flushes go-jsonnet's import cache,
+    /// so files imported by earlier evaluations are resolved again.
+    ///
+    /// When no import resolver is set, go-jsonnet's native importer evaluates the code and its
+    /// imports are found afterwards by resolving literal import paths with `FileImporter` and
+    /// the library search paths added by `jpath_add`. The graph then also contains imports which
+    /// the evaluation didn't force.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.jpath_add("testdata/lib").unwrap();
+    /// let traced = vm
+    ///     .evaluate_snippet_traced::<String>(
+    ///         "evaluate_snippet_traced.jsonnet",
+    ///         "(import 'evaluate_file.libsonnet').name",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(traced.value, "imported");
+    /// let edge = &traced.imports.imports[0];
+    /// assert_eq!(edge.kind, Some(gojsonnet::ImportKind::Import));
+    /// assert_eq!(edge.found_here, "testdata/lib/evaluate_file.libsonnet");
+    /// ```
+    pub fn evaluate_snippet_traced<T>(&self, filename: &str, code: &str) -> Result<Traced<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let traced = self.evaluate_snippet_raw_traced(filename, code)?;
+        Ok(Traced {
+            value: serde_json::from_str(&traced.value)?,
+            imports: traced.imports,
+        })
+    }
+
+    /// Evaluate a Jsonnet code and return the output of the interpreter as is with the files it
+    /// imported.
+    ///
+    /// Imports are recorded in the same way as `evaluate_snippet_traced`.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.string_output(true);
+    /// let traced = vm
+    ///     .evaluate_snippet_raw_traced(
+    ///         "testdata/evaluate_snippet_raw_traced.jsonnet",
+    ///         "(import 'lib/evaluate_file.libsonnet').name",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(traced.value, "imported\n");
+    /// assert_eq!(
+    ///     traced.imports.imports[0].found_here,
+    ///     "testdata/lib/evaluate_file.libsonnet"
+    /// );
+    /// ```
+    pub fn evaluate_snippet_raw_traced(
+        &self,
+        filename: &str,
+        code: &str,
+    ) -> Result<Traced<String>, Error> {
+        let (value, resolved) = match self.import_callback_holder {
+            Some(holder) => {
+                unsafe { (*holder).trace.replace(Some(Vec::new())) };
+                self.flush_import_cache(holder);
+                let result = self.evaluate_snippet_raw(filename, code);
+                let resolved = unsafe { (*holder).trace.replace(None) };
+                (result?, resolved.unwrap_or_default())
+            }
+            None => {
+                let value = self.evaluate_snippet_raw(filename, code)?;
+                let mut importer = FileImporter::new();
+                for jpath in &self.jpaths {
+                    importer.jpath_add(jpath.clone());
+                }
+                (value, trace::resolve_imports(filename, code, &importer))
+            }
+        };
+        Ok(Traced {
+            value,
+            imports: ImportGraph::build(filename, code, resolved),
+        })
+    }
+
+    /// Evaluate a Jsonnet file and return the result with the files it imported.
+    ///
+    /// Imports are recorded in the same way as `evaluate_snippet_traced`.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let traced = vm
+    ///     .evaluate_file_traced::<serde_json::Value, _>("testdata/evaluate_file.jsonnet")
+    ///     .unwrap();
+    /// let edge = &traced.imports.imports[0];
+    /// assert_eq!(
+    ///     edge.importer.as_deref(),
+    ///     Some("testdata/evaluate_file.jsonnet")
+    /// );
+    /// assert_eq!(edge.found_here, "testdata/lib/evaluate_file.libsonnet");
+    /// ```
+    pub fn evaluate_file_traced<T, P>(&self, path: P) -> Result<Traced<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        P: AsRef<std::path::Path>,
+    {
+        let traced = self.evaluate_file_raw_traced(path)?;
+        Ok(Traced {
+            value: serde_json::from_str(&traced.value)?,
+            imports: traced.imports,
+        })
+    }
+
+    /// Evaluate a Jsonnet file and return the output of the interpreter as is with the files it
+    /// imported.
+    ///
+    /// The file is read once and evaluated as a snippet, and imports are recorded in the same
+    /// way as `evaluate_snippet_traced`.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let traced = vm
+    ///     .evaluate_file_raw_traced("testdata/evaluate_file.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     traced.value,
+    ///     "{\n   \"bar\": \"imported\",\n   \"foo\": 3\n}\n"
+    /// );
+    /// assert_eq!(traced.imports.imports.len(), 1);
+    /// ```
+    pub fn evaluate_file_raw_traced<P>(&self, path: P) -> Result<Traced<String>, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let (filename, code) = read_source(path.as_ref())?;
+        self.evaluate_snippet_raw_traced(filename, &code)
+    }
+
+    fn evaluate_snippet_stream_list(
+        &self,
+        filename: &str,
+        code: &str,
+    ) -> Result<Vec<String>, Error> {
+        let filename_cstr = std::ffi::CString::new(filename)?;
+        let code_cstr = std::ffi::CString::new(code)?;
+        self.begin_evaluation(filename, Some(code));
+        let mut err = 0;
+        unsafe {
+            let ptr = gojsonnet_sys::jsonnet_evaluate_snippet_stream(
+                self.inner,
+                filename_cstr.as_ptr(),
+                code_cstr.as_ptr(),
+                &mut err,
+            );
+            self.take_output_list(ptr, err)
+        }
+    }
+
+    fn evaluate_file_stream_list(&self, path: &std::path::Path) -> Result<Vec<String>, Error> {
+        if self.import_policy.is_some() {
+            let (filename, code) = read_source(path)?;
+            return self.evaluate_snippet_stream_list(filename, &code);
+        }
+        let path_cstr = path_to_cstring(path)?;
+        self.begin_evaluation(&path.to_string_lossy(), None);
+        let mut err = 0;
+        unsafe {
+            let ptr = gojsonnet_sys::jsonnet_evaluate_file_stream(
+                self.inner,
+                path_cstr.as_ptr(),
                 &mut err,
             );
-            let json_str = std::ffi::CStr::from_ptr(ptr).to_string_lossy();
-            if err == 0 {
-                let result = serde_json::from_str(&json_str);
-                gojsonnet_sys::jsonnet_realloc(self.inner, ptr, 0);
-                Ok(result?)
-            } else {
-                let message = json_str.into_owned();
-                gojsonnet_sys::jsonnet_realloc(self.inner, ptr, 0);
-                Err(Error::GoJsonnetError { message })
+            self.take_output_list(ptr, err)
+        }
+    }
+
+    /// Prepare the state of import callbacks for an evaluation. `code` is `None` when go-jsonnet
+    /// reads the file, which never happens while an import policy is set.
+    fn begin_evaluation(&self, filename: &str, code: Option<&str>) {
+        self.record_source(filename, code);
+        if let (Some(policy), Some(holder)) = (&self.import_policy, self.import_callback_holder) {
+            policy.begin(filename, code.unwrap_or_default());
+            for (filename, code) in &self.code_vars {
+                policy.scan(filename, code);
+            }
+            // Check imports cached in previous evaluations again
+            self.flush_import_cache(holder);
+        }
+    }
+
+    /// Register the callback again to flush the import cache of go-jsonnet, so that imports
+    /// resolved in previous evaluations are passed to the callback again.
+    fn flush_import_cache(&self, holder: *mut ImportCallbackHolder) {
+        unsafe {
+            gojsonnet_sys::jsonnet_import_callback(
+                self.inner,
+                Some(import_callback_bridge),
+                holder as *mut std::ffi::c_void,
+            )
+        };
+    }
+
+    /// Replace the source recorded for rendering errors. `code` is `None` when go-jsonnet reads
+    /// the file.
+    #[cfg(feature = "diagnostics")]
+    fn record_source(&self, filename: &str, code: Option<&str>) {
+        self.sources.reset(filename, code);
+    }
+    #[cfg(not(feature = "diagnostics"))]
+    fn record_source(&self, _filename: &str, _code: Option<&str>) {}
+
+    /// Take the ownership of a string returned from go-jsonnet.
+    unsafe fn take_output(
+        &self,
+        ptr: *mut std::os::raw::c_char,
+        err: std::os::raw::c_int,
+    ) -> Result<String, Error> {
+        take_output(self.inner, ptr, err)
+    }
+
+    /// Take the ownership of a list of strings returned from go-jsonnet.
+    ///
+    /// Each string is terminated by NUL and the list is terminated by an empty string.
+    unsafe fn take_output_list(
+        &self,
+        ptr: *mut std::os::raw::c_char,
+        err: std::os::raw::c_int,
+    ) -> Result<Vec<String>, Error> {
+        if err != 0 {
+            return self.take_output(ptr, err).map(|_| Vec::new());
+        }
+        let mut list = Vec::new();
+        let mut p = ptr as *const std::os::raw::c_char;
+        loop {
+            let c_str = std::ffi::CStr::from_ptr(p);
+            let len = c_str.to_bytes().len();
+            if len == 0 {
+                break;
             }
+            list.push(c_str.to_string_lossy().into_owned());
+            p = p.add(len + 1);
         }
+        gojsonnet_sys::jsonnet_realloc(self.inner, ptr, 0);
+        Ok(list)
     }
 
     /// Register a native function to the interpreter.
     ///
+    /// When the callback returns `Err`, the evaluation fails with the error message prefixed by
+    /// the name of the function.
+    ///
+    /// Only null, booleans, numbers and strings can be passed as arguments because go-jsonnet's
+    /// C API cannot extract arrays and objects. Use `native_callback_json` to receive them.
+    ///
     /// ```rust
     /// let mut vm = gojsonnet::Vm::default();
-    /// vm.native_callback("hello", &["arg1"], |argv| {
+    /// let greeting = "hello".to_owned();
+    /// vm.native_callback("hello", &["arg1"], move |argv| {
     ///     let arg1 = argv[0].as_str().unwrap();
-    ///     Some(serde_json::json!(format!("hello {}", arg1)))
+    ///     Ok(serde_json::json!(format!("{} {}", greeting, arg1)))
     /// })
     /// .unwrap();
     /// #[derive(Debug, PartialEq, serde::Deserialize)]
@@ -300,11 +1161,88 @@ impl Vm {
     ///     }
     /// );
     /// ```
-    pub fn native_callback(
+    pub fn native_callback<F>(
+        &mut self,
+        name: &str,
+        params: &[&str],
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&[serde_json::Value]) -> Result<serde_json::Value, String> + 'static,
+    {
+        self.register_native_callback(name, params, Box::new(callback), false)
+    }
+
+    /// Register a native function which takes any values including arrays and objects.
+    ///
+    /// The function is exposed as the external variable `name`, which is a Jsonnet function
+    /// passing each argument through `std.manifestJsonEx` to the native function `name`. The
+    /// arguments are parsed back before the callback is called, so `std.native(name)` must be
+    /// called with JSON strings, since go-jsonnet's C API can't pass arrays and objects to it.
+    ///
+    /// Parameter names must be Jsonnet identifiers other than `std`. The external variable
+    /// `name` can't be bound by both this and `ext_var` or `ext_code`.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.native_callback_json("replicas", &["config"], |argv| {
+    ///     let config = &argv[0];
+    ///     Ok(serde_json::json!(config["replicas"].as_i64().unwrap_or(1) * 2))
+    /// })
+    /// .unwrap();
+    /// let n: i64 = vm
+    ///     .evaluate_snippet(
+    ///         "native_callback_json.jsonnet",
+    ///         "std.extVar('replicas')({ replicas: 3, ports: [80, 443] })",
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(n, 6);
+    /// ```
+    pub fn native_callback_json<F>(
+        &mut self,
+        name: &str,
+        params: &[&str],
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&[serde_json::Value]) -> Result<serde_json::Value, String> + 'static,
+    {
+        for (i, param) in params.iter().enumerate() {
+            if !is_identifier(param) || *param == "std" || params[..i].contains(param) {
+                return Err(Error::InvalidParameter {
+                    param: (*param).to_owned(),
+                });
+            }
+        }
+        if self.ext_vars.contains(name) {
+            return Err(Error::ExtVarConflict {
+                name: name.to_owned(),
+                bound_by: "ext_var or ext_code",
+            });
+        }
+        self.register_native_callback(name, params, Box::new(callback), true)?;
+        let name_literal = serde_json::Value::from(name).to_string();
+        let args: Vec<_> = params
+            .iter()
+            .map(|param| format!("std.manifestJsonEx({}, '')", param))
+            .collect();
+        let shim = format!(
+            "function({}) std.native({})({})",
+            params.join(", "),
+            name_literal,
+            args.join(", ")
+        );
+        self.bind_ext_code(name, &shim)?;
+        self.json_shims.insert(name.to_owned());
+        Ok(())
+    }
+
+    fn register_native_callback(
         &mut self,
         name: &str,
         params: &[&str],
         callback: NativeCallback,
+        json_args: bool,
     ) -> Result<(), Error> {
         let name_cstr = std::ffi::CString::new(name)?;
         let mut params_c = Vec::with_capacity(params.len());
@@ -318,8 +1256,10 @@ impl Vm {
         params_ptr.push(std::ptr::null());
         let holder = Box::into_raw(Box::new(NativeCallbackHolder {
             vm: self.inner,
+            name: name.to_owned(),
             callback,
             argc: params.len(),
+            json_args,
         }));
         let old_holder = self.native_callback_holders.insert(name.to_owned(), holder);
         unsafe {
@@ -358,9 +1298,11 @@ impl Vm {
     /// );
     /// ```
     pub fn ext_var(&mut self, key: &str, val: &str) -> Result<(), Error> {
+        self.check_json_shim(key)?;
         let key_cstr = std::ffi::CString::new(key)?;
         let val_cstr = std::ffi::CString::new(val)?;
         unsafe { gojsonnet_sys::jsonnet_ext_var(self.inner, key_cstr.as_ptr(), val_cstr.as_ptr()) };
+        self.ext_vars.insert(key.to_owned());
         Ok(())
     }
 
@@ -379,14 +1321,34 @@ impl Vm {
     /// assert_eq!(s, S { v: true });
     /// ```
     pub fn ext_code(&mut self, key: &str, val: &str) -> Result<(), Error> {
+        self.check_json_shim(key)?;
+        self.bind_ext_code(key, val)?;
+        self.ext_vars.insert(key.to_owned());
+        Ok(())
+    }
+
+    fn bind_ext_code(&mut self, key: &str, val: &str) -> Result<(), Error> {
         let key_cstr = std::ffi::CString::new(key)?;
         let val_cstr = std::ffi::CString::new(val)?;
         unsafe {
             gojsonnet_sys::jsonnet_ext_code(self.inner, key_cstr.as_ptr(), val_cstr.as_ptr())
         };
+        self.code_vars
+            .insert(format!("<extvar:{}>", key), val.to_owned());
         Ok(())
     }
 
+    fn check_json_shim(&self, key: &str) -> Result<(), Error> {
+        if self.json_shims.contains(key) {
+            Err(Error::ExtVarConflict {
+                name: key.to_owned(),
+                bound_by: "native_callback_json",
+            })
+        } else {
+            Ok(())
+        }
+    }
+
     /// Bind a Jsonnet top-level variable to the given string.
     ///
     /// ```rust
@@ -433,6 +1395,8 @@ impl Vm {
         unsafe {
             gojsonnet_sys::jsonnet_tla_code(self.inner, key_cstr.as_ptr(), val_cstr.as_ptr())
         };
+        self.code_vars
+            .insert(format!("<top-level-arg:{}>", key), val.to_owned());
         Ok(())
     }
 
@@ -445,17 +1409,24 @@ impl Vm {
     pub fn jpath_add(&mut self, path: &str) -> Result<(), Error> {
         let path_cstr = std::ffi::CString::new(path)?;
         unsafe { gojsonnet_sys::jsonnet_jpath_add(self.inner, path_cstr.as_ptr()) };
+        self.jpaths.push(path.to_owned());
+        if self.default_importer {
+            self.install_default_importer();
+        }
         Ok(())
     }
 
     /// Override the callback used to locate imports.
     ///
+    /// Library search paths added by `jpath_add` are no longer used. Use `FileImporter` to
+    /// search them from a custom resolver.
+    ///
     /// ```rust
     /// let mut vm = gojsonnet::Vm::default();
     /// vm.import_callback(|base, rel| {
     ///     Ok(gojsonnet::ImportedContent {
     ///         found_here: "import_callback.libsonnet".to_owned(),
-    ///         content: "1 + 2".to_owned(),
+    ///         content: b"1 + 2".to_vec(),
     ///     })
     /// });
     /// let s: Vec<i32> = vm
@@ -463,10 +1434,104 @@ impl Vm {
     ///     .unwrap();
     /// assert_eq!(s, vec![3]);
     /// ```
-    pub fn import_callback(&mut self, callback: ImportCallback) {
+    pub fn import_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&str, &str) -> Result<ImportedContent, ImportError> + 'static,
+    {
+        self.import_resolver(callback)
+    }
+
+    /// Override the resolver used to locate imports.
+    ///
+    /// ```rust
+    /// struct Resolver {
+    ///     libsonnets: std::collections::HashMap<String, String>,
+    /// }
+    /// impl gojsonnet::ImportResolver for Resolver {
+    ///     fn resolve(
+    ///         &self,
+    ///         _base: &str,
+    ///         rel: &str,
+    ///     ) -> Result<gojsonnet::ImportedContent, gojsonnet::ImportError> {
+    ///         match self.libsonnets.get(rel) {
+    ///             Some(content) => Ok(gojsonnet::ImportedContent {
+    ///                 found_here: rel.to_owned(),
+    ///                 content: content.as_bytes().to_vec(),
+    ///             }),
+    ///             None => Err(gojsonnet::ImportError::NotFound {
+    ///                 rel: rel.to_owned(),
+    ///             }),
+    ///         }
+    ///     }
+    /// }
+    /// let mut libsonnets = std::collections::HashMap::new();
+    /// libsonnets.insert("foo.libsonnet".to_owned(), "{ foo: 1 }".to_owned());
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.import_resolver(Resolver { libsonnets });
+    /// let s: Vec<i32> = vm
+    ///     .evaluate_snippet("import_resolver.jsonnet", "[(import 'foo.libsonnet').foo]")
+    ///     .unwrap();
+    /// assert_eq!(s, vec![1]);
+    /// ```
+    pub fn import_resolver<R>(&mut self, resolver: R)
+    where
+        R: ImportResolver + 'static,
+    {
+        self.default_importer = false;
+        self.set_import_resolver(Box::new(resolver));
+    }
+
+    /// Restrict files imported by Jsonnet code.
+    ///
+    /// The policy applies to the resolver set by `import_resolver` and `import_callback`. When
+    /// no resolver is set, `FileImporter` with the library search paths added by `jpath_add` is
+    /// installed, so go-jsonnet's default importer is never used.
+    ///
+    /// ```rust
+    /// let mut policy = gojsonnet::ImportPolicy::new();
+    /// policy.max_imports(1);
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.import_policy(policy);
+    /// let e = vm
+    ///     .evaluate_snippet::<serde_json::Value>(
+    ///         "testdata/import_policy.jsonnet",
+    ///         "[import 'evaluate_file.jsonnet', import 'lib/evaluate_file.libsonnet']",
+    ///     )
+    ///     .unwrap_err();
+    /// assert!(e.to_string().contains("too many imports"), "{}", e);
+    /// ```
+    pub fn import_policy(&mut self, policy: ImportPolicy) {
+        let policy = std::rc::Rc::new(import::PolicyEnforcer::new(policy));
+        self.import_policy = Some(policy.clone());
+        match self.import_callback_holder {
+            Some(holder) => unsafe { (*holder).policy = Some(policy) },
+            None => {
+                self.install_default_importer();
+            }
+        }
+    }
+
+    /// Install `FileImporter` which behaves like go-jsonnet's default importer.
+    fn install_default_importer(&mut self) -> *mut ImportCallbackHolder {
+        let mut importer = FileImporter::new();
+        for jpath in &self.jpaths {
+            importer.jpath_add(jpath.clone());
+        }
+        self.default_importer = true;
+        self.set_import_resolver(Box::new(importer))
+    }
+
+    fn set_import_resolver(
+        &mut self,
+        resolver: Box<dyn ImportResolver>,
+    ) -> *mut ImportCallbackHolder {
         let holder = Box::into_raw(Box::new(ImportCallbackHolder {
             vm: self.inner,
-            callback,
+            resolver,
+            trace: std::cell::RefCell::new(None),
+            policy: self.import_policy.clone(),
+            #[cfg(feature = "diagnostics")]
+            sources: self.sources.clone(),
         }));
         let old_holder = self.import_callback_holder.replace(holder);
         unsafe {
@@ -479,6 +1544,7 @@ impl Vm {
                 holder as *mut std::ffi::c_void,
             )
         };
+        holder
     }
 
     /// Set indentation level for formatting.
@@ -593,6 +1659,20 @@ impl Vm {
         unsafe { gojsonnet_sys::jsonnet_fmt_sort_imports(self.inner, v as i32) };
     }
 
+    /// Set all formatter options at once.
+    ///
+    /// ```rust
+    /// let mut options = gojsonnet::FmtOptions::default();
+    /// options.pad_objects = false;
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.fmt_options(&options);
+    /// let code = vm.fmt_snippet("fmt_options.jsonnet", "{x:1}").unwrap();
+    /// assert_eq!(code, "{x: 1}\n");
+    /// ```
+    pub fn fmt_options(&mut self, options: &FmtOptions) {
+        unsafe { options.apply(self.inner) };
+    }
+
     /// Format a Jsonnet code.
     ///
     /// ```rust
@@ -603,6 +1683,7 @@ impl Vm {
     pub fn fmt_snippet(&self, filename: &str, snippet: &str) -> Result<String, Error> {
         let filename_cstr = std::ffi::CString::new(filename)?;
         let snippet_cstr = std::ffi::CString::new(snippet)?;
+        self.record_source(filename, Some(snippet));
         let mut err = 0;
         unsafe {
             let ptr = gojsonnet_sys::jsonnet_fmt_snippet(
@@ -611,13 +1692,7 @@ impl Vm {
                 snippet_cstr.as_ptr(),
                 &mut err,
             );
-            let s = std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned();
-            gojsonnet_sys::jsonnet_realloc(self.inner, ptr, 0);
-            if err == 0 {
-                Ok(s)
-            } else {
-                Err(Error::GoJsonnetError { message: s })
-            }
+            self.take_output(ptr, err)
         }
     }
 }
@@ -662,5 +1737,358 @@ mod tests {
             e
         );
         assert!(e.to_string().contains("Unknown variable"), "e = {}", e);
+        match e {
+            super::Error::GoJsonnetError { details, .. } => {
+                assert_eq!(details.kind, super::ErrorKind::Static);
+                assert_eq!(details.message, "Unknown variable: bar");
+                let span = details.span.unwrap();
+                assert_eq!(span.filename, "evaluate_snippet_syntax_error.jsonnet");
+                assert_eq!(span.begin.line, 1);
+                assert_eq!(span.begin.column, 7);
+            }
+            _ => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn evaluate_snippet_runtime_error() {
+        let vm = super::Vm::default();
+        let e = vm
+            .evaluate_snippet::<()>(
+                "evaluate_snippet_runtime_error.jsonnet",
+                "local f(x) = error 'boom ' + x;\n{ v: f('x') }",
+            )
+            .unwrap_err();
+        match e {
+            super::Error::GoJsonnetError { details, .. } => {
+                assert_eq!(details.kind, super::ErrorKind::Runtime);
+                assert_eq!(details.message, "boom x");
+                let span = details.span.unwrap();
+                assert_eq!(span.filename, "evaluate_snippet_runtime_error.jsonnet");
+                assert_eq!(span.begin.line, 1);
+                assert!(!details.stack_trace.is_empty());
+            }
+            _ => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn evaluate_snippet_multi_error() {
+        let vm = super::Vm::default();
+        let e = vm
+            .evaluate_snippet_multi::<()>("evaluate_snippet_multi_error.jsonnet", "[1, 2]")
+            .unwrap_err();
+        assert!(
+            e.to_string().starts_with("go-jsonnet returned error: "),
+            "e = {}",
+            e
+        );
+        assert!(
+            e.to_string()
+                .contains("multi mode: top-level object was a array"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn evaluate_snippet_yaml_stream_empty() {
+        let vm = super::Vm::default();
+        let yaml = vm
+            .evaluate_snippet_yaml_stream("evaluate_snippet_yaml_stream_empty.jsonnet", "[]")
+            .unwrap();
+        assert_eq!(yaml, "");
+    }
+
+    #[test]
+    fn string_output_non_string() {
+        let mut vm = super::Vm::default();
+        vm.string_output(true);
+        let e = vm
+            .evaluate_snippet_raw("string_output_non_string.jsonnet", "{}")
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("expected string result"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn native_callback_dropped() {
+        let state = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut vm = super::Vm::default();
+        let s = state.clone();
+        vm.native_callback("count", &[], move |_| {
+            s.set(s.get() + 1);
+            Ok(serde_json::json!(s.get()))
+        })
+        .unwrap();
+        let v: Vec<i32> = vm
+            .evaluate_snippet(
+                "native_callback_dropped.jsonnet",
+                "local count = std.native('count'); [count(), count()]",
+            )
+            .unwrap();
+        assert_eq!(v, vec![1, 2]);
+        assert_eq!(std::rc::Rc::strong_count(&state), 2);
+
+        vm.native_callback("count", &[], |_| Ok(serde_json::json!(0)))
+            .unwrap();
+        assert_eq!(std::rc::Rc::strong_count(&state), 1);
+
+        let s = state.clone();
+        vm.native_callback("count", &[], move |_| Ok(serde_json::json!(s.get())))
+            .unwrap();
+        assert_eq!(std::rc::Rc::strong_count(&state), 2);
+        drop(vm);
+        assert_eq!(std::rc::Rc::strong_count(&state), 1);
+    }
+
+    #[test]
+    fn native_callback_error() {
+        let mut vm = super::Vm::default();
+        vm.native_callback("lookup", &["key"], |argv| {
+            Err(format!("key {} not found", argv[0].as_str().unwrap()))
+        })
+        .unwrap();
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_error.jsonnet",
+                "local lookup = std.native('lookup');\n{ v: lookup('x') }",
+            )
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("lookup: key x not found"),
+            "e = {}",
+            e
+        );
+        assert!(
+            e.to_string().contains("native_callback_error.jsonnet:2:"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn native_callback_json_argument() {
+        let mut vm = super::Vm::default();
+        vm.native_callback("keys", &["obj"], |argv| {
+            let obj: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(argv[0].as_str().unwrap()).map_err(|e| e.to_string())?;
+            Ok(serde_json::json!(obj.keys().collect::<Vec<_>>()))
+        })
+        .unwrap();
+        let v: Vec<String> = vm
+            .evaluate_snippet(
+                "native_callback_json_argument.jsonnet",
+                "std.native('keys')(std.manifestJson({a: [1], b: {c: null}}))",
+            )
+            .unwrap();
+        assert_eq!(v, vec!["a", "b"]);
+
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_json_argument.jsonnet",
+                "std.native('keys')({a: 1})",
+            )
+            .unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("keys: array and object arguments are not supported"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn native_callback_json_shim() {
+        let mut vm = super::Vm::default();
+        vm.native_callback_json("merge", &["a", "b"], |argv| {
+            let mut merged = argv[0].as_object().cloned().unwrap_or_default();
+            merged.insert("b".to_owned(), argv[1].clone());
+            Ok(serde_json::Value::Object(merged))
+        })
+        .unwrap();
+        let v: serde_json::Value = vm
+            .evaluate_snippet(
+                "native_callback_json_shim.jsonnet",
+                "std.extVar('merge')({a: [1, 'x', null]}, {c: {d: true}})",
+            )
+            .unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({"a": [1, "x", null], "b": {"c": {"d": true}}})
+        );
+
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_json_shim.jsonnet",
+                "std.native('merge')('{', 1)",
+            )
+            .unwrap_err();
+        assert!(e.to_string().contains("merge: "), "e = {}", e);
+    }
+
+    #[test]
+    fn native_callback_json_invalid_parameters() {
+        let mut vm = super::Vm::default();
+        for params in &[&["std"][..], &["a b"], &["local"], &["a", "a"], &["1a"]] {
+            let e = vm
+                .native_callback_json("f", params, |_| Ok(serde_json::Value::Null))
+                .unwrap_err();
+            assert!(
+                matches!(e, super::Error::InvalidParameter { .. }),
+                "e = {}",
+                e
+            );
+        }
+        assert!(super::is_identifier("_a1"));
+    }
+
+    #[test]
+    fn native_callback_json_ext_var_conflict() {
+        let mut vm = super::Vm::default();
+        vm.ext_var("v", "1").unwrap();
+        let e = vm
+            .native_callback_json("v", &["a"], |_| Ok(serde_json::Value::Null))
+            .unwrap_err();
+        assert!(
+            matches!(e, super::Error::ExtVarConflict { ref name, .. } if name == "v"),
+            "e = {}",
+            e
+        );
+
+        vm.native_callback_json("f", &["a"], |argv| Ok(argv[0].clone()))
+            .unwrap();
+        assert!(vm.ext_var("f", "1").is_err());
+        assert!(vm.ext_code("f", "1").is_err());
+        // Registering the same function again replaces the shim
+        vm.native_callback_json("f", &["b"], |argv| Ok(argv[0].clone()))
+            .unwrap();
+        let v: serde_json::Value = vm
+            .evaluate_snippet(
+                "native_callback_json_ext_var_conflict.jsonnet",
+                "[std.extVar('v'), std.extVar('f')({ x: 1 })]",
+            )
+            .unwrap();
+        assert_eq!(v, serde_json::json!(["1", {"x": 1}]));
+
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_json_ext_var_conflict.jsonnet",
+                "std.native('f')({ x: 1 })",
+            )
+            .unwrap_err();
+        assert!(e.to_string().contains("call std.extVar('f')"), "e = {}", e);
+    }
+
+    #[test]
+    fn native_callback_panic() {
+        let mut vm = super::Vm::default();
+        vm.native_callback("boom", &[], |_| panic!("boom!"))
+            .unwrap();
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_panic.jsonnet",
+                "std.native('boom')()",
+            )
+            .unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("boom: native callback panicked: boom!"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn native_callback_invalid_result() {
+        let mut vm = super::Vm::default();
+        vm.native_callback("nul", &[], |_| Ok(serde_json::json!({"a": "x\0y"})))
+            .unwrap();
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "native_callback_invalid_result.jsonnet",
+                "std.native('nul')()",
+            )
+            .unwrap_err();
+        assert!(e.to_string().contains("nul: nul byte found"), "e = {}", e);
+    }
+
+    #[test]
+    fn import_callback_binary() {
+        let mut importer = crate::MemoryImporter::new();
+        importer.add_file("a.bin", vec![0xff, 0x01]);
+        importer.add_file("nul.bin", vec![0x01, 0x00]);
+        let mut vm = super::Vm::default();
+        vm.import_resolver(importer);
+        let v: Vec<u8> = vm
+            .evaluate_snippet("import_callback_binary.jsonnet", "importbin 'a.bin'")
+            .unwrap();
+        assert_eq!(v, vec![0xff, 0x01]);
+        let e = vm
+            .evaluate_snippet::<Vec<u8>>("import_callback_binary.jsonnet", "importbin 'nul.bin'")
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("nul.bin contains NUL bytes"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn import_callback_panic() {
+        let mut vm = super::Vm::default();
+        vm.import_callback(|_, _| panic!("boom!"));
+        let e = vm
+            .evaluate_snippet::<serde_json::Value>(
+                "import_callback_panic.jsonnet",
+                "import 'foo.libsonnet'",
+            )
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("import callback panicked: boom!"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn evaluate_file_not_found() {
+        let vm = super::Vm::default();
+        let e = vm
+            .evaluate_file::<(), _>("testdata/no_such_file.jsonnet")
+            .unwrap_err();
+        assert!(
+            e.to_string().contains("testdata/no_such_file.jsonnet"),
+            "e = {}",
+            e
+        );
+    }
+
+    #[test]
+    fn evaluate_snippet_traced_twice() {
+        let mut vm = super::Vm::default();
+        vm.jpath_add("testdata").unwrap();
+        let code = "(import 'lib/evaluate_file.libsonnet').name";
+        let s: String = vm.evaluate_snippet("traced.jsonnet", code).unwrap();
+        assert_eq!(s, "imported");
+        // Imports cached by go-jsonnet are still recorded
+        for _ in 0..2 {
+            let traced = vm
+                .evaluate_snippet_traced::<String>("traced.jsonnet", code)
+                .unwrap();
+            assert_eq!(
+                traced.imports.files(),
+                vec!["traced.jsonnet", "testdata/lib/evaluate_file.libsonnet"]
+            );
+        }
+        // Library search paths added later are also searched
+        vm.jpath_add("testdata/lib").unwrap();
+        let s: String = vm
+            .evaluate_snippet("traced.jsonnet", "(import 'evaluate_file.libsonnet').name")
+            .unwrap();
+        assert_eq!(s, "imported");
     }
 }
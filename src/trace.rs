@@ -0,0 +1,567 @@
+/// Kind of import expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportKind {
+    /// `import`, evaluated as Jsonnet.
+    Import,
+    /// `importstr`, imported as a string.
+    ImportStr,
+    /// `importbin`, imported as an array of bytes.
+    ImportBin,
+}
+impl ImportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Import => "import",
+            Self::ImportStr => "importstr",
+            Self::ImportBin => "importbin",
+        }
+    }
+}
+
+/// An import resolved during an evaluation.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ImportEdge {
+    /// File containing the import expression.
+    ///
+    /// go-jsonnet only passes the directory of the importing file to the import callback, so
+    /// the importing file and the kind are found by scanning the sources of the evaluated
+    /// files. Both are `None` when the import expression couldn't be found.
+    pub importer: Option<String>,
+    pub kind: Option<ImportKind>,
+    /// Directory of the importing file, as passed to the import callback.
+    pub base: String,
+    /// Imported path as written in the code.
+    pub rel: String,
+    /// Resolved path of the imported file.
+    pub found_here: String,
+}
+
+/// Files touched by an evaluation and the imports between them.
+///
+/// The graph can be exported as JSON with serde.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ImportGraph {
+    /// Filename of the evaluated code.
+    pub root: String,
+    /// Imports in the order they were resolved.
+    pub imports: Vec<ImportEdge>,
+}
+
+/// Result of an evaluation with the files it touched.
+#[derive(Debug, Clone)]
+pub struct Traced<T> {
+    pub value: T,
+    pub imports: ImportGraph,
+}
+
+/// Import recorded by the import callback.
+#[derive(Debug, Clone)]
+pub(crate) struct ResolvedImport {
+    pub(crate) base: String,
+    pub(crate) rel: String,
+    pub(crate) found_here: String,
+    pub(crate) content: String,
+}
+
+impl ImportGraph {
+    /// Build the graph from imports recorded during the evaluation of `root_code`.
+    pub(crate) fn build(root: &str, root_code: &str, resolved: Vec<ResolvedImport>) -> Self {
+        let index: std::collections::HashMap<_, _> = resolved
+            .iter()
+            .enumerate()
+            .map(|(i, r)| ((r.base.as_str(), r.rel.as_str()), i))
+            .collect();
+        let mut matched = vec![false; resolved.len()];
+        let mut visited = std::collections::HashSet::new();
+        let mut queue = std::collections::VecDeque::new();
+        let mut imports = Vec::new();
+        visited.insert(root);
+        queue.push_back((root, root_code));
+        while let Some((file, code)) = queue.pop_front() {
+            let base = dir_of(file);
+            for (kind, rel) in scan_imports(code) {
+                let i = match index.get(&(base, rel.as_str())) {
+                    Some(&i) => i,
+                    None => continue,
+                };
+                let r = &resolved[i];
+                matched[i] = true;
+                imports.push(ImportEdge {
+                    importer: Some(file.to_owned()),
+                    kind: Some(kind),
+                    base: r.base.clone(),
+                    rel: r.rel.clone(),
+                    found_here: r.found_here.clone(),
+                });
+                if kind == ImportKind::Import && visited.insert(&r.found_here) {
+                    queue.push_back((&r.found_here, &r.content));
+                }
+            }
+        }
+        for (r, matched) in resolved.iter().zip(matched) {
+            if !matched {
+                imports.push(ImportEdge {
+                    importer: None,
+                    kind: None,
+                    base: r.base.clone(),
+                    rel: r.rel.clone(),
+                    found_here: r.found_here.clone(),
+                });
+            }
+        }
+        Self {
+            root: root.to_owned(),
+            imports,
+        }
+    }
+
+    /// Return all files touched by the evaluation, the root first.
+    ///
+    /// ```rust
+    /// let mut vm = gojsonnet::Vm::default();
+    /// let traced = vm
+    ///     .evaluate_file_traced::<serde_json::Value, _>("testdata/evaluate_file.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     traced.imports.files(),
+    ///     vec![
+    ///         "testdata/evaluate_file.jsonnet",
+    ///         "testdata/lib/evaluate_file.libsonnet"
+    ///     ]
+    /// );
+    /// ```
+    pub fn files(&self) -> Vec<&str> {
+        let mut seen = std::collections::HashSet::new();
+        std::iter::once(self.root.as_str())
+            .chain(self.imports.iter().map(|edge| edge.found_here.as_str()))
+            .filter(|file| seen.insert(*file))
+            .collect()
+    }
+
+    /// Export the graph in Graphviz DOT format.
+    ///
+    /// ```rust
+    /// let mut importer = gojsonnet::MemoryImporter::new();
+    /// importer.add_file("lib.libsonnet", "{ text: importstr 'text.txt' }");
+    /// importer.add_file("text.txt", "hello");
+    /// let mut vm = gojsonnet::Vm::default();
+    /// vm.import_resolver(importer);
+    /// let traced = vm
+    ///     .evaluate_snippet_traced::<String>("main.jsonnet", "(import 'lib.libsonnet').text")
+    ///     .unwrap();
+    /// assert_eq!(traced.value, "hello");
+    /// assert_eq!(
+    ///     traced.imports.to_dot(),
+    ///     r#"digraph imports {
+    ///   "main.jsonnet";
+    ///   "lib.libsonnet";
+    ///   "text.txt";
+    ///   "main.jsonnet" -> "lib.libsonnet" [label="import"];
+    ///   "lib.libsonnet" -> "text.txt" [label="importstr"];
+    /// }
+    /// "#
+    /// );
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph imports {\n".to_owned();
+        for file in self.files() {
+            dot.push_str(&format!("  {};\n", dot_id(file)));
+        }
+        for edge in &self.imports {
+            if let (Some(importer), Some(kind)) = (&edge.importer, edge.kind) {
+                dot.push_str(&format!(
+                    "  {} -> {} [label=\"{}\"];\n",
+                    dot_id(importer),
+                    dot_id(&edge.found_here),
+                    kind.as_str()
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render a Make-compatible depfile which makes `target` depend on all files touched by the
+    /// evaluation.
+    ///
+    /// ```rust
+    /// let vm = gojsonnet::Vm::default();
+    /// let traced = vm
+    ///     .evaluate_file_traced::<serde_json::Value, _>("testdata/evaluate_file.jsonnet")
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     traced.imports.to_depfile("out/evaluate file.json"),
+    ///     "out/evaluate\\ file.json: testdata/evaluate_file.jsonnet \\\n  testdata/lib/evaluate_file.libsonnet\n"
+    /// );
+    /// ```
+    pub fn to_depfile(&self, target: &str) -> String {
+        let mut depfile = format!("{}:", escape_make(target));
+        let files = self.files();
+        for (i, file) in files.iter().enumerate() {
+            if i != 0 {
+                depfile.push_str(" \\\n ");
+            }
+            depfile.push(' ');
+            depfile.push_str(&escape_make(file));
+        }
+        depfile.push('\n');
+        depfile
+    }
+}
+
+fn dot_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn escape_make(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Return the directory part of the filename in the same way as go-jsonnet passes it to the
+/// import callback, i.e. with the trailing slash.
+pub(crate) fn dir_of(filename: &str) -> &str {
+    match filename.rfind('/') {
+        Some(i) => &filename[..=i],
+        None => "",
+    }
+}
+
+/// Resolve imports with literal paths reachable from `root_code` without evaluating it.
+///
+/// go-jsonnet's native importer can't be observed through the C API, so the files it would
+/// import are found with `resolver` instead. Imports which the evaluation doesn't force are
+/// also resolved, and imports which can't be resolved are left out.
+pub(crate) fn resolve_imports(
+    root: &str,
+    root_code: &str,
+    resolver: &dyn crate::ImportResolver,
+) -> Vec<ResolvedImport> {
+    let mut resolved = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut queue = std::collections::VecDeque::new();
+    visited.insert(root.to_owned());
+    queue.push_back((root.to_owned(), root_code.to_owned()));
+    while let Some((file, code)) = queue.pop_front() {
+        let base = dir_of(&file);
+        for (kind, rel) in scan_imports(&code) {
+            if !seen.insert((base.to_owned(), rel.clone())) {
+                continue;
+            }
+            let imported_content = match resolver.resolve(base, &rel) {
+                Ok(imported_content) => imported_content,
+                Err(_) => continue,
+            };
+            let content = String::from_utf8_lossy(&imported_content.content).into_owned();
+            if kind == ImportKind::Import && visited.insert(imported_content.found_here.clone()) {
+                queue.push_back((imported_content.found_here.clone(), content.clone()));
+            }
+            resolved.push(ResolvedImport {
+                base: base.to_owned(),
+                rel,
+                found_here: imported_content.found_here,
+                content,
+            });
+        }
+    }
+    resolved
+}
+
+/// Find import expressions with literal paths in a Jsonnet code.
+pub(crate) fn scan_imports(code: &str) -> Vec<(ImportKind, String)> {
+    let bytes = code.as_bytes();
+    let mut imports = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if let Some(end) = skip_comment(code, i) {
+            i = end;
+        } else if let Some((_, end)) = string_literal(code, i) {
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            let kind = match &code[start..i] {
+                "import" => ImportKind::Import,
+                "importstr" => ImportKind::ImportStr,
+                "importbin" => ImportKind::ImportBin,
+                _ => continue,
+            };
+            let mut j = i;
+            loop {
+                if let Some(end) = skip_comment(code, j) {
+                    j = end;
+                } else if j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                    j += 1;
+                } else {
+                    break;
+                }
+            }
+            if let Some((rel, end)) = string_literal(code, j) {
+                imports.push((kind, rel));
+                i = end;
+            }
+        } else {
+            i += code[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    imports
+}
+
+/// Return the end of the comment starting at `i`.
+fn skip_comment(code: &str, i: usize) -> Option<usize> {
+    let rest = &code[i..];
+    let (terminator, body_start) = if rest.starts_with('#') {
+        ("\n", 1)
+    } else if rest.starts_with("//") {
+        ("\n", 2)
+    } else if rest.starts_with("/*") {
+        ("*/", 2)
+    } else {
+        return None;
+    };
+    Some(
+        rest[body_start..]
+            .find(terminator)
+            .map(|j| i + body_start + j + terminator.len())
+            .unwrap_or_else(|| code.len()),
+    )
+}
+
+/// Parse the string literal starting at `i` and return its value and end.
+fn string_literal(code: &str, i: usize) -> Option<(String, usize)> {
+    let rest = &code[i..];
+    if let Some(body) = rest.strip_prefix("|||") {
+        // Text blocks are skipped without decoding
+        let body_start = body.find('\n')? + 1;
+        let mut offset = body_start;
+        for line in body[body_start..].split_inclusive('\n') {
+            if line.trim_start().starts_with("|||") {
+                let end = i + 3 + offset + line.find("|||")? + 3;
+                return Some((String::new(), end));
+            }
+            offset += line.len();
+        }
+        return Some((String::new(), code.len()));
+    }
+    let (verbatim, quote, start) = match rest.chars().next()? {
+        '@' => match rest[1..].chars().next()? {
+            q @ '\'' | q @ '"' => (true, q, 2),
+            _ => return None,
+        },
+        q @ '\'' | q @ '"' => (false, q, 1),
+        _ => return None,
+    };
+    let mut value = String::new();
+    let mut chars = rest[start..].char_indices().peekable();
+    while let Some((j, c)) = chars.next() {
+        if c == quote {
+            if verbatim && chars.peek().map(|&(_, c)| c) == Some(quote) {
+                chars.next();
+                value.push(quote);
+                continue;
+            }
+            return Some((value, i + start + j + c.len_utf8()));
+        } else if c == '\\' && !verbatim {
+            match chars.next()?.1 {
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = (0..4)
+                        .filter_map(|_| chars.next().map(|(_, c)| c))
+                        .collect();
+                    value.extend(
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32),
+                    );
+                }
+                c => value.push(c),
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    Some((value, code.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_imports() {
+        let code = r#"
+            // import 'comment.libsonnet'
+            local a = import "a.libsonnet";
+            local s = 'import "string.libsonnet" ✓';
+            /* importstr 'comment.txt' */
+            {
+              b: importstr @'b''s.txt',
+              c: importbin /* comment */ 'cA.bin',
+              d: |||
+                import 'text_block.libsonnet'
+              |||,
+              e: (import 'e.libsonnet').e,
+              importer: 1,
+            }
+        "#;
+        assert_eq!(
+            scan_imports(code),
+            vec![
+                (ImportKind::Import, "a.libsonnet".to_owned()),
+                (ImportKind::ImportStr, "b's.txt".to_owned()),
+                (ImportKind::ImportBin, "cA.bin".to_owned()),
+                (ImportKind::Import, "e.libsonnet".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_import_graph() {
+        let resolved = vec![
+            ResolvedImport {
+                base: "app/".to_owned(),
+                rel: "../lib/a.libsonnet".to_owned(),
+                found_here: "lib/a.libsonnet".to_owned(),
+                content: "{ b: import 'b.libsonnet', c: importstr 'c.txt' }".to_owned(),
+            },
+            ResolvedImport {
+                base: "lib/".to_owned(),
+                rel: "b.libsonnet".to_owned(),
+                found_here: "lib/b.libsonnet".to_owned(),
+                content: "importstr 'c.txt'".to_owned(),
+            },
+            ResolvedImport {
+                base: "lib/".to_owned(),
+                rel: "c.txt".to_owned(),
+                found_here: "lib/c.txt".to_owned(),
+                content: "import 'd.libsonnet'".to_owned(),
+            },
+            ResolvedImport {
+                base: "app/".to_owned(),
+                rel: "computed.libsonnet".to_owned(),
+                found_here: "app/computed.libsonnet".to_owned(),
+                content: "{}".to_owned(),
+            },
+        ];
+        let graph = ImportGraph::build("app/main.jsonnet", "import '../lib/a.libsonnet'", resolved);
+        let edges: Vec<_> = graph
+            .imports
+            .iter()
+            .map(|edge| {
+                (
+                    edge.importer.as_deref(),
+                    edge.kind,
+                    edge.found_here.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                (
+                    Some("app/main.jsonnet"),
+                    Some(ImportKind::Import),
+                    "lib/a.libsonnet"
+                ),
+                (
+                    Some("lib/a.libsonnet"),
+                    Some(ImportKind::Import),
+                    "lib/b.libsonnet"
+                ),
+                (
+                    Some("lib/a.libsonnet"),
+                    Some(ImportKind::ImportStr),
+                    "lib/c.txt"
+                ),
+                (
+                    Some("lib/b.libsonnet"),
+                    Some(ImportKind::ImportStr),
+                    "lib/c.txt"
+                ),
+                (None, None, "app/computed.libsonnet"),
+            ]
+        );
+        assert_eq!(
+            graph.files(),
+            vec![
+                "app/main.jsonnet",
+                "lib/a.libsonnet",
+                "lib/b.libsonnet",
+                "lib/c.txt",
+                "app/computed.libsonnet"
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&graph.imports[2]).unwrap(),
+            serde_json::json!({
+                "importer": "lib/a.libsonnet",
+                "kind": "importstr",
+                "base": "lib/",
+                "rel": "c.txt",
+                "found_here": "lib/c.txt",
+            })
+        );
+    }
+
+    #[test]
+    fn resolve_imports_statically() {
+        let mut importer = crate::MemoryImporter::new();
+        importer.add_file(
+            "lib/a.libsonnet",
+            "{ b: import 'b.libsonnet', c: importbin 'c.bin' }",
+        );
+        importer.add_file("lib/b.libsonnet", "import 'a.libsonnet'");
+        importer.add_file("lib/c.bin", vec![0xff, 0]);
+        let code =
+            "[import 'lib/a.libsonnet', import 'lib/a.libsonnet', import 'missing.libsonnet']";
+        let resolved = resolve_imports("main.jsonnet", code, &importer);
+        let graph = ImportGraph::build("main.jsonnet", code, resolved);
+        assert_eq!(
+            graph.files(),
+            vec![
+                "main.jsonnet",
+                "lib/a.libsonnet",
+                "lib/b.libsonnet",
+                "lib/c.bin"
+            ]
+        );
+        assert_eq!(graph.imports.len(), 5);
+    }
+
+    #[test]
+    fn depfile() {
+        let graph = ImportGraph {
+            root: "main.jsonnet".to_owned(),
+            imports: vec![ImportEdge {
+                importer: Some("main.jsonnet".to_owned()),
+                kind: Some(ImportKind::ImportStr),
+                base: "".to_owned(),
+                rel: "$price #1.txt".to_owned(),
+                found_here: "$price #1.txt".to_owned(),
+            }],
+        };
+        assert_eq!(
+            graph.to_depfile("out.json"),
+            "out.json: main.jsonnet \\\n  $$price\\ \\#1.txt\n"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+fn jsonnet() -> std::process::Command {
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_jsonnet"));
+    command.env_remove("JSONNET_PATH");
+    command
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("gojsonnet-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn stdout_json(output: std::process::Output) -> serde_json::Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn evaluate_file() {
+    let output = jsonnet()
+        .arg("testdata/evaluate_file.jsonnet")
+        .output()
+        .unwrap();
+    assert_eq!(
+        stdout_json(output),
+        serde_json::json!({"foo": 3, "bar": "imported"})
+    );
+}
+
+#[test]
+fn ext_vars() {
+    let output = jsonnet()
+        .env("GOJSONNET_CLI_EXT_STR", "from env")
+        .args([
+            "-V",
+            "GOJSONNET_CLI_EXT_STR",
+            "--ext-str-file",
+            "str=testdata/lib/evaluate_file.libsonnet",
+            "--ext-code-file",
+            "code=testdata/lib/evaluate_file.libsonnet",
+            "-e",
+            "[std.extVar('GOJSONNET_CLI_EXT_STR'), std.extVar('str'), std.extVar('code').name]",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(
+        stdout_json(output),
+        serde_json::json!(["from env", "{\n  name: 'imported',\n}\n", "imported"])
+    );
+}
+
+#[test]
+fn tla_files() {
+    let output = jsonnet()
+        .args([
+            "--tla-str-file",
+            "str=testdata/lib/evaluate_file.libsonnet",
+            "--tla-code-file",
+            "code=testdata/lib/evaluate_file.libsonnet",
+            "-e",
+            "function(str, code) [std.length(str) > 0, code.name]",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(stdout_json(output), serde_json::json!([true, "imported"]));
+}
+
+#[test]
+fn undefined_env_var() {
+    let output = jsonnet()
+        .args(["-V", "GOJSONNET_CLI_UNDEFINED", "-e", "null"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR: environment variable GOJSONNET_CLI_UNDEFINED was undefined.\n"
+    );
+}
+
+#[test]
+fn jsonnet_path_precedence() {
+    let dir = temp_dir("jpath");
+    for name in &["a", "b", "c"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        std::fs::write(dir.join(name).join("lib.libsonnet"), format!("'{}'", name)).unwrap();
+    }
+    let jsonnet_path = format!("{}:{}", dir.join("a").display(), dir.join("b").display());
+
+    // Left-most wins in JSONNET_PATH
+    let output = jsonnet()
+        .env("JSONNET_PATH", &jsonnet_path)
+        .args(["-e", "import 'lib.libsonnet'"])
+        .output()
+        .unwrap();
+    assert_eq!(stdout_json(output), "a");
+
+    // -J takes precedence over JSONNET_PATH
+    let output = jsonnet()
+        .env("JSONNET_PATH", &jsonnet_path)
+        .arg("-J")
+        .arg(dir.join("c"))
+        .args(["-e", "import 'lib.libsonnet'"])
+        .output()
+        .unwrap();
+    assert_eq!(stdout_json(output), "c");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn multi() {
+    let dir = temp_dir("multi");
+    let out = dir.join("out");
+    let output = jsonnet()
+        .arg("-m")
+        .arg(&out)
+        .args(["-c", "-e", "{'a.json': 1, 'b/c.json': [2]}"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{}\n{}\n",
+            out.join("a.json").display(),
+            out.join("b/c.json").display()
+        )
+    );
+    let read_json = |name: &str| -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(out.join(name)).unwrap()).unwrap()
+    };
+    assert_eq!(read_json("a.json"), serde_json::json!(1));
+    assert_eq!(read_json("b/c.json"), serde_json::json!([2]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn error_exit_status() {
+    let output = jsonnet().args(["-e", "error 'boom'"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("boom"), "{}", stderr);
+}
+
+#[test]
+fn depfile_importbin() {
+    let dir = temp_dir("depfile");
+    std::fs::write(dir.join("data.bin"), [0x00, 0xff, 0x01]).unwrap();
+    std::fs::write(
+        dir.join("main.jsonnet"),
+        "{ bytes: importbin 'data.bin', lib: import 'lib.libsonnet' }",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib.libsonnet"),
+        "std.length(importbin 'data.bin')",
+    )
+    .unwrap();
+    let out = dir.join("out.json");
+    let depfile = dir.join("out.json.d");
+    let output = jsonnet()
+        .arg("-o")
+        .arg(&out)
+        .arg("--depfile")
+        .arg(&depfile)
+        .arg(dir.join("main.jsonnet"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&std::fs::read(&out).unwrap()).unwrap(),
+        serde_json::json!({"bytes": [0, 255, 1], "lib": 3})
+    );
+    assert_eq!(
+        std::fs::read_to_string(&depfile).unwrap(),
+        format!(
+            "{}: {} \\\n  {} \\\n  {}\n",
+            out.display(),
+            dir.join("main.jsonnet").display(),
+            dir.join("data.bin").display(),
+            dir.join("lib.libsonnet").display()
+        )
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}